@@ -0,0 +1,177 @@
+//! 扫描基准: 报告扫描与解映射的耗时和堆内存峰值、映射文件大小, 以及不同线程数下的吞吐量
+//!
+//! ```sh
+//! cargo bench --bench scan                # 生成临时目录树
+//! cargo bench --bench scan -- /mnt/share  # 扫描已有目录
+//! ```
+//!
+//! 临时目录树的规模可通过 `DIRMAP_BENCH_DIRS` 与 `DIRMAP_BENCH_FILES` (每个目录的文件数) 调整
+
+use std::{
+    alloc::{GlobalAlloc, Layout, System},
+    env, fs,
+    path::Path,
+    sync::atomic::{AtomicUsize, Ordering},
+    time::{Duration, Instant},
+};
+
+use dirmap::{DirMap, ScanOptions, scan, unmap};
+
+/// 统计当前与峰值堆内存的分配器
+struct Counting;
+
+static CURRENT: AtomicUsize = AtomicUsize::new(0);
+static PEAK: AtomicUsize = AtomicUsize::new(0);
+
+fn grow(size: usize) {
+    let now = CURRENT.fetch_add(size, Ordering::Relaxed) + size;
+    PEAK.fetch_max(now, Ordering::Relaxed);
+}
+
+unsafe impl GlobalAlloc for Counting {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        let ptr = unsafe { System.alloc(layout) };
+        if !ptr.is_null() {
+            grow(layout.size());
+        }
+        ptr
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        unsafe { System.dealloc(ptr, layout) };
+        CURRENT.fetch_sub(layout.size(), Ordering::Relaxed);
+    }
+
+    unsafe fn realloc(&self, ptr: *mut u8, layout: Layout, new_size: usize) -> *mut u8 {
+        let new = unsafe { System.realloc(ptr, layout, new_size) };
+        if !new.is_null() {
+            CURRENT.fetch_sub(layout.size(), Ordering::Relaxed);
+            grow(new_size);
+        }
+        new
+    }
+}
+
+#[global_allocator]
+static ALLOCATOR: Counting = Counting;
+
+fn env_or(key: &str, default: usize) -> usize {
+    env::var(key)
+        .ok()
+        .and_then(|value| value.parse().ok())
+        .unwrap_or(default)
+}
+
+/// 每层 16 个子目录的树, 共 `dirs` 个目录, 每个目录 `files` 个文件
+fn generate(root: &Path, dirs: usize, files: usize) {
+    let mut paths = vec![root.to_path_buf()];
+    let mut next = 0;
+    while paths.len() < dirs {
+        let parent = paths[next].clone();
+        for i in 0..16.min(dirs - paths.len()) {
+            let path = parent.join(format!("d{i}"));
+            fs::create_dir(&path).expect("创建目录失败");
+            paths.push(path);
+        }
+        next += 1;
+    }
+    for dir in &paths {
+        for i in 0..files {
+            fs::write(dir.join(format!("f{i}.txt")), "x").expect("写入文件失败");
+        }
+    }
+}
+
+fn mib(bytes: usize) -> f64 {
+    bytes as f64 / (1 << 20) as f64
+}
+
+/// 执行 `f`, 返回结果、耗时、期间的堆峰值与结束后仍占用的堆内存
+fn tracked<T>(f: impl FnOnce() -> T) -> (T, Duration, usize, usize) {
+    let before = CURRENT.load(Ordering::Relaxed);
+    PEAK.store(before, Ordering::Relaxed);
+    let timer = Instant::now();
+    let value = f();
+    let elapsed = timer.elapsed();
+    let peak = PEAK.load(Ordering::Relaxed) - before;
+    let retained = CURRENT.load(Ordering::Relaxed).saturating_sub(before);
+    (value, elapsed, peak, retained)
+}
+
+fn measure(name: &str, start: &str) -> DirMap {
+    let (dirs, elapsed, peak, retained) =
+        tracked(|| scan(start, ScanOptions::default()).expect("扫描失败"));
+    let root = dirs.root();
+    println!(
+        "{name}: {} 个目录, {} 个文件, 耗时 {elapsed:.2?}, 堆峰值 {:.1} MiB, 目录表 {:.1} MiB",
+        dirs.len(),
+        root.total_file_count(),
+        mib(peak),
+        mib(retained),
+    );
+    dirs
+}
+
+/// 编码后的映射文件大小, 以及解映射的耗时与内存
+fn decode(dirs: &DirMap) {
+    let raw = dirs.encode().expect("编码失败");
+    let (decoded, elapsed, peak, retained) = tracked(|| unmap(&raw).expect("解映射失败"));
+    drop(decoded);
+    println!(
+        "映射文件 {:.1} KiB, 解映射耗时 {elapsed:.2?}, 堆峰值 {:.1} MiB, 目录表 {:.1} MiB",
+        raw.len() as f64 / 1024.0,
+        mib(peak),
+        mib(retained),
+    );
+}
+
+/// 线程数从 1 倍增到可用核数, 报告每秒处理的文件数
+fn scaling(start: &str) {
+    let cores = std::thread::available_parallelism().map_or(1, |n| n.get());
+    let threads = std::iter::successors(Some(1), |&n| (n < cores).then(|| (n * 2).min(cores)));
+    let mut baseline = None;
+    for threads in threads {
+        let pool = rayon::ThreadPoolBuilder::new()
+            .num_threads(threads)
+            .build()
+            .expect("创建线程池失败");
+        let timer = Instant::now();
+        let dirs = pool
+            .install(|| scan(start, ScanOptions::default()))
+            .expect("扫描失败");
+        let elapsed = timer.elapsed().as_secs_f64();
+        let files = dirs.root().total_file_count() as f64;
+        let speedup = *baseline.get_or_insert(elapsed) / elapsed;
+        println!(
+            "{threads:>3} 线程: {:.0} 文件/秒, 加速比 {speedup:.2}",
+            files / elapsed
+        );
+    }
+}
+
+fn main() {
+    // cargo bench 会传入 `--bench`
+    let paths: Vec<_> = env::args()
+        .skip(1)
+        .filter(|a| !a.starts_with("--"))
+        .collect();
+    if !paths.is_empty() {
+        for path in &paths {
+            decode(&measure(path, path));
+            scaling(path);
+        }
+        return;
+    }
+
+    let tmp = tempfile::tempdir().expect("创建临时目录失败");
+    let (dirs, files) = (
+        env_or("DIRMAP_BENCH_DIRS", 2000),
+        env_or("DIRMAP_BENCH_FILES", 50),
+    );
+    generate(tmp.path(), dirs, files);
+    let start = tmp.path().to_str().expect("路径无效");
+    // 首次扫描预热目录缓存
+    drop(measure("预热", start));
+    decode(&measure("扫描", start));
+    scaling(start);
+}
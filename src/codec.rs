@@ -0,0 +1,187 @@
+//! 当前格式的映射文件内容: 目录按编号存储, 目录名与文件名只在名称表中出现一次,
+//! 父目录、递归统计与分类统计在解码时重新计算, 只有未展开目录中汇总内容的分类统计需要保存
+//!
+//! 名称表保存名称的原始字节, 不是合法 UTF-8 的名称解码后标记为原始名称
+
+use std::{borrow::Cow, collections::HashMap, sync::Arc};
+
+use anyhow::{Result, anyhow};
+use bincode::{Decode, Encode, config};
+
+use crate::{
+    Boundary, Digest, Dir, DirId, DirMap, File, HardLink, Header, ImageInfo, KindStats, KindTotals,
+    Link, Metadata, ScanError, Totals, name,
+};
+
+#[derive(Encode, Decode)]
+struct DirRecord {
+    /// 名称表下标
+    name: u32,
+    files: Vec<FileRecord>,
+    children: Vec<DirId>,
+    links: Vec<Link>,
+    boundary: Option<Boundary>,
+    unexpanded: Totals,
+    unexpanded_stats: Option<StatsRecord>,
+    metadata: Option<Metadata>,
+}
+
+/// 未展开目录中汇总内容的分类统计
+#[derive(Encode, Decode)]
+struct StatsRecord {
+    kinds: Vec<(u8, KindTotals)>,
+    extensions: Vec<(String, KindTotals)>,
+}
+
+impl StatsRecord {
+    fn new(stats: &KindStats) -> Self {
+        StatsRecord {
+            kinds: stats
+                .kinds()
+                .map(|(kind, totals)| (kind.code(), totals))
+                .collect(),
+            extensions: stats
+                .extensions()
+                .map(|(ext, totals)| (ext.to_string(), totals))
+                .collect(),
+        }
+    }
+
+    fn into_stats(self) -> Box<KindStats> {
+        Box::new(KindStats::from_entries(self.kinds, self.extensions))
+    }
+}
+
+#[derive(Encode, Decode)]
+struct FileRecord {
+    typ: u8,
+    /// 名称表下标
+    name: u32,
+    size: u64,
+    disk_size: u64,
+    hardlink: Option<HardLink>,
+    metadata: Option<Metadata>,
+    digest: Option<Digest>,
+    /// 按内容识别的类型编码
+    content: Option<u8>,
+    image: Option<ImageInfo>,
+    phash: Option<u64>,
+}
+
+/// 名称表, 原始字节相同的名称共用一个下标
+#[derive(Default)]
+struct Names<'a> {
+    index: HashMap<Cow<'a, [u8]>, u32>,
+    names: Vec<Cow<'a, [u8]>>,
+}
+
+impl<'a> Names<'a> {
+    fn intern(&mut self, name: &'a str) -> u32 {
+        let bytes = name::to_bytes(name);
+        *self.index.entry(bytes.clone()).or_insert_with(|| {
+            self.names.push(bytes);
+            self.names.len() as u32 - 1
+        })
+    }
+}
+
+pub(crate) fn encode(map: &DirMap) -> Result<Vec<u8>> {
+    let mut names = Names::default();
+    let records: Vec<_> = map
+        .dirs
+        .iter()
+        .map(|dir| DirRecord {
+            name: names.intern(&dir.name),
+            files: dir
+                .file
+                .iter()
+                .map(|file| FileRecord {
+                    typ: file.typ,
+                    name: names.intern(&file.name),
+                    size: file.size,
+                    disk_size: file.disk_size,
+                    hardlink: file.hardlink,
+                    metadata: file.metadata.as_deref().copied(),
+                    digest: file.digest.as_deref().copied(),
+                    content: file.content,
+                    image: file.image.as_deref().copied(),
+                    phash: file.phash,
+                })
+                .collect(),
+            children: dir.children.clone(),
+            links: dir.links.clone(),
+            boundary: dir.boundary,
+            unexpanded: dir.unexpanded,
+            unexpanded_stats: dir.unexpanded_stats.as_deref().map(StatsRecord::new),
+            metadata: dir.metadata.as_deref().copied(),
+        })
+        .collect();
+    Ok(bincode::encode_to_vec(
+        (&map.header, &names.names, &records, &map.errors),
+        config::standard(),
+    )?)
+}
+
+pub(crate) fn decode(data: &[u8]) -> Result<DirMap> {
+    type Archive = (Header, Vec<Vec<u8>>, Vec<DirRecord>, Vec<ScanError>);
+    let ((header, names, records, errors), _): (Archive, _) =
+        bincode::decode_from_slice(data, config::standard())?;
+    let names: Vec<(Arc<str>, bool)> = names
+        .into_iter()
+        .map(|bytes| {
+            let (name, raw) = name::from_bytes(bytes);
+            (Arc::from(name), raw)
+        })
+        .collect();
+    let lookup = |index: u32| {
+        names
+            .get(index as usize)
+            .cloned()
+            .ok_or_else(|| anyhow!("映射文件损坏: 名称编号越界: {index}"))
+    };
+
+    let mut dirs = Vec::with_capacity(records.len());
+    for record in records {
+        let (name, raw) = lookup(record.name)?;
+        let mut dir = Dir {
+            name,
+            raw,
+            children: record.children,
+            links: record.links,
+            boundary: record.boundary,
+            unexpanded: record.unexpanded,
+            unexpanded_stats: record.unexpanded_stats.map(StatsRecord::into_stats),
+            metadata: record.metadata.map(Box::new),
+            ..Default::default()
+        };
+        for file in record.files {
+            let (name, raw) = lookup(file.name)?;
+            dir.push_file(File {
+                typ: file.typ,
+                raw,
+                name,
+                size: file.size,
+                disk_size: file.disk_size,
+                hardlink: file.hardlink,
+                metadata: file.metadata.map(Box::new),
+                digest: file.digest.map(Box::new),
+                content: file.content,
+                image: file.image.map(Box::new),
+                phash: file.phash,
+            });
+        }
+        dirs.push(dir);
+    }
+
+    for index in 0..dirs.len() {
+        for child in dirs[index].children.clone() {
+            let dir = dirs
+                .get_mut(child.index())
+                .filter(|dir| dir.parent.is_none() && child != DirId::ROOT)
+                .ok_or_else(|| anyhow!("映射文件损坏: 子目录编号无效: {}", child.0))?;
+            dir.parent = Some(DirId(index as u32));
+        }
+    }
+
+    DirMap::with_totals(header, dirs, errors)
+}
@@ -0,0 +1,150 @@
+//! 文件内容摘要
+//!
+//! 扫描结束后按大小预筛: 只有存在相同大小的其他文件时才可能重复, 只读取这些文件的内容.
+//! 候选文件由 rayon 并行计算, 同一物理文件的多个硬链接只读取一次
+
+use std::{
+    collections::HashMap,
+    fmt, fs,
+    io::{self, Read},
+    path::{Path, PathBuf},
+};
+
+use anyhow::{Result, bail};
+use bincode::{Decode, Encode};
+use rayon::prelude::*;
+use serde::{Deserialize, Serialize};
+
+use crate::{Dir, DirId, ErrorKind, HashMode, ScanError, name};
+
+/// BLAKE3 内容摘要
+#[derive(
+    Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord, Encode, Decode, Serialize, Deserialize,
+)]
+pub struct Digest(pub(crate) [u8; 32]);
+
+impl Digest {
+    pub fn as_bytes(&self) -> &[u8; 32] {
+        &self.0
+    }
+
+    /// 计算文件内容的摘要
+    pub fn of_file(path: &Path) -> io::Result<Self> {
+        let mut hasher = blake3::Hasher::new();
+        hasher.update_reader(fs::File::open(path)?)?;
+        Ok(Digest(*hasher.finalize().as_bytes()))
+    }
+
+    /// 计算文件前 `len` 字节的摘要, 用于快速排除内容不同的文件
+    pub(crate) fn of_prefix(path: &Path, len: u64) -> io::Result<Self> {
+        let mut hasher = blake3::Hasher::new();
+        hasher.update_reader(fs::File::open(path)?.take(len))?;
+        Ok(Digest(*hasher.finalize().as_bytes()))
+    }
+}
+
+impl fmt::Display for Digest {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        self.0.iter().try_for_each(|byte| write!(f, "{byte:02x}"))
+    }
+}
+
+impl fmt::Debug for Digest {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "Digest({self})")
+    }
+}
+
+/// 为需要摘要的文件计算并写入摘要, 返回容错扫描时无法读取的文件
+///
+/// `dirs` 须按深度优先顺序编号, 父目录排在子目录之前
+pub(crate) fn hash_files(
+    start: &Path,
+    dirs: &mut [Dir],
+    mode: HashMode,
+    tolerant: bool,
+) -> Result<Vec<ScanError>> {
+    if mode == HashMode::None {
+        return Ok(Vec::new());
+    }
+
+    // 同一物理文件只计一次大小
+    let mut sizes: HashMap<u64, usize> = HashMap::new();
+    for file in dirs.iter().flat_map(|dir| &dir.file) {
+        if file.hardlink.is_none_or(|link| link.primary) {
+            *sizes.entry(file.size).or_default() += 1;
+        }
+    }
+    let wanted = |size: u64| match mode {
+        HashMode::None => false,
+        HashMode::Duplicates => size > 0 && sizes[&size] > 1,
+        HashMode::All => true,
+    };
+
+    // 以硬链接 (设备号, inode 号) 或文件位置为键, 每组只读取第一个路径
+    let mut groups: HashMap<(u64, u64), Vec<(DirId, usize)>> = HashMap::new();
+    let mut jobs = Vec::new();
+    for (id, dir) in dirs.iter().enumerate() {
+        let id = DirId(id as u32);
+        for (index, file) in dir.file.iter().enumerate() {
+            if !wanted(file.size) {
+                continue;
+            }
+            match file.hardlink {
+                Some(link) => groups.entry(link.id()).or_default().push((id, index)),
+                None => jobs.push(vec![(id, index)]),
+            }
+        }
+    }
+    jobs.extend(groups.into_values());
+
+    let paths = dir_paths(start, dirs);
+    let file_path = |(id, index): (DirId, usize)| {
+        let file = &dirs[id.index()].file[index];
+        paths[id.index()].join(name::to_os(file.raw_name()))
+    };
+    let results: Vec<_> = jobs
+        .par_iter()
+        .map(|job| {
+            let path = file_path(job[0]);
+            let digest = Digest::of_file(&path);
+            (path, digest)
+        })
+        .collect();
+
+    let mut errors = Vec::new();
+    for (job, (path, digest)) in jobs.into_iter().zip(results) {
+        match digest {
+            Ok(digest) => {
+                for (id, index) in job {
+                    dirs[id.index()].file[index].digest = Some(Box::new(digest));
+                }
+            }
+            Err(e) => {
+                let err = ScanError::new(
+                    name::relative_path(start, &path),
+                    ErrorKind::from(e.kind()),
+                    e.to_string(),
+                );
+                if !tolerant {
+                    bail!("扫描失败: {err}");
+                }
+                errors.push(err);
+            }
+        }
+    }
+    Ok(errors)
+}
+
+/// 各目录的系统路径, 由扫描起始路径与原始名称拼接
+fn dir_paths(start: &Path, dirs: &[Dir]) -> Vec<PathBuf> {
+    let mut paths: Vec<PathBuf> = Vec::with_capacity(dirs.len());
+    for dir in dirs {
+        let path = match dir.parent {
+            Some(parent) => paths[parent.index()].join(name::to_os(dir.raw_name())),
+            None => start.to_path_buf(),
+        };
+        paths.push(path);
+    }
+    paths
+}
@@ -0,0 +1,289 @@
+//! 基于映射查找内容重复的文件
+//!
+//! 先按大小分组, 再比较文件开头部分的摘要, 最后才读取完整内容, 大部分文件只需读取一小段.
+//! 同一物理文件的多个硬链接不占用额外空间, 只取计入大小的那个路径参与比较
+
+use std::{
+    collections::{HashMap, HashSet},
+    io,
+    path::Path,
+};
+
+use rayon::prelude::*;
+
+use crate::{Digest, DirId, DirMap, ErrorKind, ScanError, join, name};
+
+/// 只比较开头部分时读取的字节数, 不超过它的文件直接计算完整摘要
+const PREFIX_LEN: u64 = 16 * 1024;
+
+/// 查找重复文件的选项
+#[derive(Debug, Clone, Copy, Default)]
+pub struct DupeOptions {
+    /// 直接使用映射中已有的摘要, 不再读取这些文件; 文件在扫描后被修改时结果可能过时
+    pub reuse_digests: bool,
+    /// 忽略小于该大小的文件, 空文件总是忽略
+    pub min_size: u64,
+}
+
+/// 一组内容相同的文件
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DuplicateSet {
+    pub digest: Digest,
+    /// 单个文件的大小
+    pub size: u64,
+    /// 相对扫描根目录的路径, 按路径排序, 首个路径视为保留的副本
+    pub files: Vec<String>,
+}
+
+impl DuplicateSet {
+    /// 除保留的副本外其余副本占用的空间
+    pub fn wasted(&self) -> u64 {
+        self.size * (self.files.len() as u64 - 1)
+    }
+}
+
+/// 重复文件的查找结果
+#[derive(Debug, Clone, Default)]
+pub struct Duplicates {
+    /// 按浪费空间从大到小排序
+    pub sets: Vec<DuplicateSet>,
+    /// 各目录中多余副本直接占用的空间, 从大到小排序
+    pub dirs: Vec<(DirId, u64)>,
+    /// 无法读取的文件, 这些文件不参与比较
+    pub errors: Vec<ScanError>,
+}
+
+impl Duplicates {
+    pub fn wasted(&self) -> u64 {
+        self.sets.iter().map(DuplicateSet::wasted).sum()
+    }
+}
+
+struct Candidate {
+    dir: DirId,
+    index: usize,
+    size: u64,
+    digest: Option<Digest>,
+}
+
+impl DirMap {
+    /// 查找内容相同的文件, 除复用的摘要外需要从扫描根目录读取文件内容
+    pub fn duplicates(&self, options: DupeOptions) -> Duplicates {
+        let mut candidates = Vec::new();
+        for (dir, entry) in self.iter() {
+            for (index, file) in entry.files().enumerate() {
+                if file.size() == 0
+                    || file.size() < options.min_size
+                    || file.hardlink().is_some_and(|link| !link.is_primary())
+                {
+                    continue;
+                }
+                candidates.push(Candidate {
+                    dir,
+                    index,
+                    size: file.size(),
+                    digest: file.digest().copied().filter(|_| options.reuse_digests),
+                });
+            }
+        }
+        let mut sizes: HashMap<u64, usize> = HashMap::new();
+        for candidate in &candidates {
+            *sizes.entry(candidate.size).or_default() += 1;
+        }
+        candidates.retain(|candidate| sizes[&candidate.size] > 1);
+
+        let mut errors = Vec::new();
+
+        // 开头部分不同的文件内容必然不同, 但同大小的文件中有已知摘要时仍需完整比较
+        let known: HashSet<u64> = candidates
+            .iter()
+            .filter(|candidate| candidate.digest.is_some())
+            .map(|candidate| candidate.size)
+            .collect();
+        let (large, mut pending): (Vec<_>, Vec<_>) = candidates
+            .into_iter()
+            .partition(|candidate| candidate.digest.is_none() && candidate.size > PREFIX_LEN);
+        let prefixes = self.hash_candidates(&large, |path| Digest::of_prefix(path, PREFIX_LEN));
+        let mut large: Vec<_> = large
+            .into_iter()
+            .zip(prefixes)
+            .filter_map(|(candidate, prefix)| match prefix {
+                Ok(prefix) => Some((candidate, prefix)),
+                Err(e) => {
+                    errors.push(e);
+                    None
+                }
+            })
+            .collect();
+        let mut counts: HashMap<(u64, Digest), usize> = HashMap::new();
+        for (candidate, prefix) in &large {
+            *counts.entry((candidate.size, *prefix)).or_default() += 1;
+        }
+        large.retain(|(candidate, prefix)| {
+            known.contains(&candidate.size) || counts[&(candidate.size, *prefix)] > 1
+        });
+        pending.extend(large.into_iter().map(|(candidate, _)| candidate));
+
+        let (unhashed, hashed): (Vec<_>, Vec<_>) = pending
+            .into_iter()
+            .partition(|candidate| candidate.digest.is_none());
+        let digests = self.hash_candidates(&unhashed, Digest::of_file);
+        let mut groups: HashMap<(u64, Digest), Vec<(DirId, usize)>> = HashMap::new();
+        for candidate in hashed {
+            let key = (candidate.size, candidate.digest.expect("已有摘要"));
+            groups
+                .entry(key)
+                .or_default()
+                .push((candidate.dir, candidate.index));
+        }
+        for (candidate, digest) in unhashed.into_iter().zip(digests) {
+            match digest {
+                Ok(digest) => groups
+                    .entry((candidate.size, digest))
+                    .or_default()
+                    .push((candidate.dir, candidate.index)),
+                Err(e) => errors.push(e),
+            }
+        }
+
+        let mut wasted: HashMap<DirId, u64> = HashMap::new();
+        let mut sets: Vec<_> = groups
+            .into_iter()
+            .filter(|(_, files)| files.len() > 1)
+            .map(|((size, digest), files)| {
+                let mut files: Vec<_> = files
+                    .into_iter()
+                    .map(|(dir, index)| (self.file_path(dir, index), dir))
+                    .collect();
+                files.sort();
+                for (_, dir) in &files[1..] {
+                    *wasted.entry(*dir).or_default() += size;
+                }
+                DuplicateSet {
+                    digest,
+                    size,
+                    files: files.into_iter().map(|(path, _)| path).collect(),
+                }
+            })
+            .collect();
+        sets.sort_by(|a, b| {
+            b.wasted()
+                .cmp(&a.wasted())
+                .then_with(|| a.files.cmp(&b.files))
+        });
+        let mut dirs: Vec<_> = wasted.into_iter().collect();
+        dirs.sort_by_key(|&(dir, size)| (std::cmp::Reverse(size), self.path(dir)));
+        errors.sort_by(|a, b| a.path().cmp(b.path()));
+
+        Duplicates { sets, dirs, errors }
+    }
+
+    /// 并行读取候选文件, 结果与输入一一对应
+    fn hash_candidates(
+        &self,
+        candidates: &[Candidate],
+        hash: impl Fn(&Path) -> io::Result<Digest> + Sync,
+    ) -> Vec<Result<Digest, ScanError>> {
+        let paths: Vec<_> = candidates
+            .iter()
+            .map(|candidate| {
+                let file = &self.dirs[candidate.dir.index()].file[candidate.index];
+                self.os_path(candidate.dir)
+                    .join(name::to_os(file.raw_name()))
+            })
+            .collect();
+        paths
+            .par_iter()
+            .zip(candidates)
+            .map(|(path, candidate)| {
+                hash(path).map_err(|e| {
+                    ScanError::new(
+                        self.file_path(candidate.dir, candidate.index),
+                        ErrorKind::from(e.kind()),
+                        e.to_string(),
+                    )
+                })
+            })
+            .collect()
+    }
+
+    fn file_path(&self, dir: DirId, index: usize) -> String {
+        join(&self.path(dir), &self.dirs[dir.index()].file[index].name)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+
+    use super::*;
+    use crate::{HashMode, ScanOptions, scan};
+
+    #[test]
+    fn test_duplicates() {
+        let tmp = tempfile::tempdir().expect("创建临时目录失败");
+        fs::create_dir(tmp.path().join("copy")).expect("创建目录失败");
+        let large = vec![7u8; PREFIX_LEN as usize * 2];
+        let mut other = large.clone();
+        *other.last_mut().expect("内容为空") = 8;
+        for (path, content) in [
+            ("a.png", &b"same"[..]),
+            ("copy/a.png", b"same"),
+            ("copy/b.png", b"same"),
+            ("c.png", b"diff"),
+            ("big", &large),
+            ("copy/big", &large),
+            ("big-other", &other),
+            ("empty", b""),
+            ("copy/empty", b""),
+        ] {
+            fs::write(tmp.path().join(path), content).expect("写入文件失败");
+        }
+        #[cfg(unix)]
+        fs::hard_link(tmp.path().join("c.png"), tmp.path().join("copy/c.png"))
+            .expect("创建硬链接失败");
+
+        let start = tmp.path().to_str().expect("路径无效");
+        let dirs = scan(start, ScanOptions::default()).expect("扫描失败");
+        let dupes = dirs.duplicates(DupeOptions::default());
+        assert!(dupes.errors.is_empty());
+        let files: Vec<_> = dupes.sets.iter().map(|set| set.files.clone()).collect();
+        assert_eq!(
+            files,
+            [
+                vec!["big".to_string(), "copy/big".to_string()],
+                vec![
+                    "a.png".to_string(),
+                    "copy/a.png".to_string(),
+                    "copy/b.png".to_string()
+                ],
+            ]
+        );
+        assert_eq!(dupes.wasted(), PREFIX_LEN * 2 + 8);
+        let copy = dirs.id("copy").expect("目录不存在");
+        assert_eq!(dupes.dirs, [(copy, PREFIX_LEN * 2 + 8)]);
+
+        // 复用映射中的摘要时不再读取文件
+        let options = ScanOptions {
+            hash: HashMode::All,
+            ..Default::default()
+        };
+        let dirs = scan(start, options).expect("扫描失败");
+        fs::remove_file(tmp.path().join("copy/a.png")).expect("删除文件失败");
+        let reused = dirs.duplicates(DupeOptions {
+            reuse_digests: true,
+            ..Default::default()
+        });
+        assert!(reused.errors.is_empty());
+        assert_eq!(reused.sets, dupes.sets);
+        let fresh = dirs.duplicates(DupeOptions {
+            min_size: 5,
+            ..Default::default()
+        });
+        assert_eq!(fresh.sets.len(), 1);
+        assert!(fresh.errors.is_empty());
+        let fresh = dirs.duplicates(DupeOptions::default());
+        assert_eq!(fresh.errors.len(), 1);
+        assert_eq!(fresh.errors[0].kind(), ErrorKind::NotFound);
+    }
+}
@@ -0,0 +1,403 @@
+//! 解映射后的目录表的树级修改, 所有操作都会同步更新祖先目录的统计
+//!
+//! 新建的目录名与文件名按 `name::normalize` 转为规范的转义形式, 编码再解码后保持不变
+
+use std::collections::HashSet;
+
+use anyhow::{Result, anyhow, bail};
+
+use crate::{Dir, DirId, DirMap, File, KindStats, KindTotals, Totals, name};
+
+impl DirMap {
+    /// 创建目录, 缺失的中间目录会一并创建, 已存在时不做修改
+    ///
+    /// 路径可能是相对路径也可能以扫描根目录开头时, 取已存在部分更深的解释, 相同时视为完整路径;
+    /// 含 `\` 时同样取更深的解释, 相同时 `\` 视为名称的一部分
+    pub fn create_dir(&mut self, path: &str) -> Result<DirId> {
+        let path = self.destination(path);
+        let names: Vec<_> = crate::components(&path).map(name::normalize).collect();
+        let mut id = DirId::ROOT;
+        for name in names {
+            id = match self.child(id, &name) {
+                Some(child) => child,
+                None => {
+                    let child = DirId(self.dirs.len() as u32);
+                    let dir = Dir {
+                        raw: name::is_raw(&name),
+                        name: name.into(),
+                        parent: Some(id),
+                        ..Default::default()
+                    };
+                    self.apply_to_ancestors(id, |parent| parent.total += dir.subtree_totals());
+                    self.dirs[id.index()].children.push(child);
+                    self.dirs.push(dir);
+                    child
+                }
+            };
+        }
+        Ok(id)
+    }
+
+    /// 在目录下插入文件, 同名文件会被替换
+    pub fn insert_file(&mut self, dir: &str, file: File) -> Result<()> {
+        let id = self.key(dir)?;
+        if let Some(index) = self.dirs[id.index()]
+            .file
+            .iter()
+            .position(|f| f.name == file.name)
+        {
+            self.remove_file_at(id, index);
+        }
+
+        let delta = Totals {
+            size: file.counted_size(),
+            disk_size: file.counted_disk_size(),
+            files: 1,
+            ..Default::default()
+        };
+        let stats = KindStats::of_file(&file);
+        self.apply_to_ancestors(id, |dir| {
+            dir.total += delta;
+            dir.stats += &stats;
+        });
+        let target = &mut self.dirs[id.index()];
+        target.own_size += file.counted_size();
+        target.own_disk_size += file.counted_disk_size();
+        let link = file.hardlink;
+        target.file.push(file);
+        if let Some(link) = link {
+            self.select_primaries(HashSet::from([link.id()]));
+        }
+        Ok(())
+    }
+
+    /// 按路径删除文件, 返回被删除的文件
+    pub fn remove_file(&mut self, path: &str) -> Result<File> {
+        let (id, index) = self
+            .find_file(path)
+            .ok_or_else(|| anyhow!("文件不存在: {path}"))?;
+        Ok(self.remove_file_at(id, index))
+    }
+
+    /// 删除目录及其所有子孙目录, 不能删除根目录
+    pub fn remove_dir(&mut self, path: &str) -> Result<()> {
+        let id = self.key(path)?;
+        let parent = self.parent_of(id, path)?;
+
+        let delta = self.dirs[id.index()].subtree_totals();
+        let stats = std::mem::take(&mut self.dirs[id.index()].stats);
+        let subtree = self.subtree(id);
+        let mut removed = vec![false; self.dirs.len()];
+        for sub in &subtree {
+            removed[sub.index()] = true;
+        }
+        let removed_links = self.hardlink_ids(&subtree);
+        self.dirs[parent.index()].children.retain(|&c| c != id);
+        self.apply_to_ancestors(parent, |dir| {
+            dir.total -= delta;
+            dir.stats -= &stats;
+        });
+        self.compact(&removed);
+        self.select_primaries(removed_links);
+        Ok(())
+    }
+
+    /// 移动或重命名目录, 子孙目录随之移动, 目标的父目录必须存在
+    ///
+    /// 目标路径按 [`create_dir`](Self::create_dir) 的规则解释
+    pub fn rename_dir(&mut self, from: &str, to: &str) -> Result<()> {
+        let id = self.key(from)?;
+        let old_parent = self.parent_of(id, from)?;
+        let destination = self.destination(to);
+        let (new_parent, name) = self
+            .locate_in(&destination)
+            .ok_or_else(|| anyhow!("目标的父目录不存在: {to}"))?;
+        let name = name::normalize(name);
+        if self.child(new_parent, &name).is_some() {
+            bail!("目标目录已存在: {to}");
+        }
+        if self.ancestors(new_parent).any(|a| a == id) {
+            bail!("不能将目录移动到自身之下: {from} -> {to}");
+        }
+
+        let delta = self.dirs[id.index()].subtree_totals();
+        let stats = self.dirs[id.index()].stats.clone();
+        self.dirs[old_parent.index()].children.retain(|&c| c != id);
+        self.apply_to_ancestors(old_parent, |dir| {
+            dir.total -= delta;
+            dir.stats -= &stats;
+        });
+
+        let dir = &mut self.dirs[id.index()];
+        dir.raw = name::is_raw(&name);
+        dir.name = name.into();
+        dir.parent = Some(new_parent);
+        self.dirs[new_parent.index()].children.push(id);
+        self.apply_to_ancestors(new_parent, |dir| {
+            dir.total += delta;
+            dir.stats += &stats;
+        });
+        // 移动后路径顺序改变, 子树中的硬链接可能不再排在最前
+        let links = self.hardlink_ids(&self.subtree(id));
+        self.select_primaries(links);
+        Ok(())
+    }
+
+    fn remove_file_at(&mut self, id: DirId, index: usize) -> File {
+        let target = &mut self.dirs[id.index()];
+        let file = target.file.swap_remove(index);
+        target.own_size -= file.counted_size();
+        target.own_disk_size -= file.counted_disk_size();
+
+        let delta = Totals {
+            size: file.counted_size(),
+            disk_size: file.counted_disk_size(),
+            files: 1,
+            ..Default::default()
+        };
+        let stats = KindStats::of_file(&file);
+        self.apply_to_ancestors(id, |dir| {
+            dir.total -= delta;
+            dir.stats -= &stats;
+        });
+        if let Some(link) = file.hardlink.filter(|link| link.primary) {
+            self.select_primaries(HashSet::from([link.id()]));
+        }
+        file
+    }
+
+    /// 重新选出各组硬链接中计入大小的路径, 与扫描时相同, 取路径排序最前的一个
+    ///
+    /// 删除、移动或插入硬链接后路径顺序可能改变, 相关的组都要重新选择
+    fn select_primaries(&mut self, ids: HashSet<(u64, u64)>) {
+        if ids.is_empty() {
+            return;
+        }
+        let groups = crate::hardlink_groups(&self.dirs);
+        for id in ids {
+            for (i, &(dir, index)) in groups.get(&id).into_iter().flatten().enumerate() {
+                self.set_primary(dir, index, i == 0);
+            }
+        }
+    }
+
+    fn set_primary(&mut self, dir: DirId, index: usize, primary: bool) {
+        let target = &mut self.dirs[dir.index()];
+        let file = &mut target.file[index];
+        match &mut file.hardlink {
+            Some(link) if link.primary != primary => link.primary = primary,
+            _ => return,
+        }
+        // 文件数不变, 只增减大小
+        let totals = KindTotals {
+            size: file.size,
+            disk_size: file.disk_size,
+            ..Default::default()
+        };
+        let delta = Totals {
+            size: totals.size,
+            disk_size: totals.disk_size,
+            ..Default::default()
+        };
+        let stats = KindStats::of_entry(file.typ, file.name(), totals);
+        if primary {
+            target.own_size += totals.size;
+            target.own_disk_size += totals.disk_size;
+            self.apply_to_ancestors(dir, |dir| {
+                dir.total += delta;
+                dir.stats += &stats;
+            });
+        } else {
+            target.own_size -= totals.size;
+            target.own_disk_size -= totals.disk_size;
+            self.apply_to_ancestors(dir, |dir| {
+                dir.total -= delta;
+                dir.stats -= &stats;
+            });
+        }
+    }
+
+    fn key(&self, path: &str) -> Result<DirId> {
+        self.id(path).ok_or_else(|| anyhow!("目录不存在: {path}"))
+    }
+
+    fn parent_of(&self, id: DirId, path: &str) -> Result<DirId> {
+        self.dirs[id.index()]
+            .parent
+            .ok_or_else(|| anyhow!("不能修改根目录: {path}"))
+    }
+
+    /// 目录自身及其所有祖先目录, 由近及远
+    fn ancestors(&self, id: DirId) -> impl Iterator<Item = DirId> {
+        std::iter::successors(Some(id), |id| self.dirs[id.index()].parent)
+    }
+
+    /// 从 `id` 开始逐级向上更新递归统计, 直到根目录, 并按子目录重新确定分类统计是否完整
+    fn apply_to_ancestors(&mut self, id: DirId, f: impl Fn(&mut Dir)) {
+        let mut current = Some(id);
+        while let Some(id) = current {
+            let dir = &self.dirs[id.index()];
+            let incomplete = dir.lacks_unexpanded_stats()
+                || dir
+                    .children
+                    .iter()
+                    .any(|child| !self.dirs[child.index()].stats.is_complete());
+            let dir = &mut self.dirs[id.index()];
+            f(dir);
+            dir.stats.set_incomplete(incomplete);
+            current = dir.parent;
+        }
+    }
+
+    /// 目录中的文件涉及的硬链接
+    fn hardlink_ids(&self, ids: &[DirId]) -> HashSet<(u64, u64)> {
+        ids.iter()
+            .flat_map(|id| &self.dirs[id.index()].file)
+            .filter_map(|f| f.hardlink)
+            .map(|link| link.id())
+            .collect()
+    }
+
+    /// 目录自身及所有子孙目录
+    fn subtree(&self, id: DirId) -> Vec<DirId> {
+        let mut ids = Vec::new();
+        let mut stack = vec![id];
+        while let Some(id) = stack.pop() {
+            stack.extend(self.dirs[id.index()].children());
+            ids.push(id);
+        }
+        ids
+    }
+
+    /// 删除标记的目录并重新编号, 其余目录保持原有顺序
+    fn compact(&mut self, removed: &[bool]) {
+        let mut remap = Vec::with_capacity(removed.len());
+        let mut next = 0;
+        for &removed in removed {
+            remap.push(DirId(next));
+            if !removed {
+                next += 1;
+            }
+        }
+        let dirs = std::mem::take(&mut self.dirs);
+        self.dirs = dirs
+            .into_iter()
+            .zip(removed)
+            .filter(|(_, removed)| !**removed)
+            .map(|(mut dir, _)| {
+                dir.parent = dir.parent.map(|p| remap[p.index()]);
+                for child in &mut dir.children {
+                    *child = remap[child.index()];
+                }
+                dir
+            })
+            .collect();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{DirMap, File, HardLink, Header, ScanOptions};
+
+    fn sample() -> DirMap {
+        let mut dirs = DirMap::new(Header::new("root".to_string(), ScanOptions::default()));
+        dirs.create_dir("root/a/b").expect("创建目录失败");
+        dirs.insert_file("root/a", File::new("x.png", 10))
+            .expect("插入文件失败");
+        dirs.insert_file("root/a/b", File::new("y.gif", 5))
+            .expect("插入文件失败");
+        dirs
+    }
+
+    fn consistent(dirs: &DirMap) -> bool {
+        let totals = dirs.calc_size().expect("计算大小失败");
+        totals.len() == dirs.len()
+            && totals
+                .iter()
+                .zip(dirs.iter())
+                .all(|(total, (_, dir))| dir.totals() == *total)
+            && DirMap::with_totals(dirs.header.clone(), dirs.dirs.clone(), Vec::new())
+                .expect("计算统计失败")
+                .iter()
+                .zip(dirs.iter())
+                .all(|((_, fresh), (_, dir))| fresh.kind_stats() == dir.kind_stats())
+    }
+
+    #[test]
+    fn test_insert_and_remove_file() {
+        let mut dirs = sample();
+        let root = dirs.root();
+        assert_eq!((root.total_size(), root.total_file_count()), (15, 2));
+        assert_eq!(root.total_dir_count(), 2);
+
+        dirs.insert_file("root/a/b", File::new("y.gif", 7))
+            .expect("替换文件失败");
+        assert_eq!(dirs.get("root/a").expect("目录不存在").total_size(), 17);
+
+        let removed = dirs.remove_file("root/a/x.png").expect("删除文件失败");
+        assert_eq!(removed.size(), 10);
+        assert_eq!(dirs.root().total_size(), 7);
+        assert!(consistent(&dirs));
+    }
+
+    #[test]
+    fn test_remove_dir() {
+        let mut dirs = sample();
+        dirs.remove_dir("root/a/b").expect("删除目录失败");
+        assert!(!dirs.contains("root/a/b"));
+        let root = dirs.root();
+        assert_eq!((root.total_size(), root.total_dir_count()), (10, 1));
+        assert!(dirs.remove_dir("root").is_err());
+        assert!(consistent(&dirs));
+    }
+
+    #[test]
+    fn test_rename_dir() {
+        let mut dirs = sample();
+        dirs.create_dir("root/c").expect("创建目录失败");
+        dirs.rename_dir("root/a", "root/c/a2")
+            .expect("移动目录失败");
+
+        assert!(!dirs.contains("root/a"));
+        assert!(dirs.file("root/c/a2/b/y.gif").is_some());
+        assert_eq!(dirs.get("root/c").expect("目录不存在").total_size(), 15);
+        assert!(dirs.rename_dir("root/c", "root/c/a2/d").is_err());
+        assert!(consistent(&dirs));
+    }
+
+    #[test]
+    fn test_hardlink_primary() {
+        let mut dirs = DirMap::new(Header::new("root".to_string(), ScanOptions::default()));
+        dirs.create_dir("a").expect("创建目录失败");
+        dirs.create_dir("b").expect("创建目录失败");
+        let link = |name| {
+            let mut file = File::new(name, 4);
+            file.hardlink = Some(HardLink {
+                dev: 1,
+                ino: 1,
+                primary: false,
+            });
+            file
+        };
+        let primary = |dirs: &DirMap, path| {
+            let file = dirs.file(path).expect("文件不存在");
+            file.hardlink().expect("不是硬链接").is_primary()
+        };
+        dirs.insert_file("b", link("y")).expect("插入文件失败");
+        dirs.insert_file("a", link("x")).expect("插入文件失败");
+        assert!(primary(&dirs, "a/x") && !primary(&dirs, "b/y"));
+        assert_eq!(dirs.root().total_size(), 4);
+
+        // 移动后 `b/y` 排在 `c/x` 之前
+        dirs.rename_dir("a", "c").expect("移动目录失败");
+        assert!(primary(&dirs, "b/y") && !primary(&dirs, "c/x"));
+        assert_eq!(dirs.get("b").expect("目录不存在").total_size(), 4);
+        assert_eq!(dirs.root().total_size(), 4);
+        assert!(consistent(&dirs));
+
+        dirs.remove_dir("b").expect("删除目录失败");
+        assert!(primary(&dirs, "c/x"));
+        assert_eq!(dirs.root().total_size(), 4);
+        assert!(consistent(&dirs));
+    }
+}
@@ -0,0 +1,82 @@
+use std::{collections::HashMap, fmt, io};
+
+use bincode::{Decode, Encode};
+use serde::{Deserialize, Serialize};
+
+/// 扫描错误的类别
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Encode, Decode, Serialize, Deserialize)]
+pub enum ErrorKind {
+    PermissionDenied,
+    NotFound,
+    /// 符号链接循环
+    Loop,
+    Other,
+}
+
+impl fmt::Display for ErrorKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            ErrorKind::PermissionDenied => "权限不足",
+            ErrorKind::NotFound => "路径不存在",
+            ErrorKind::Loop => "符号链接循环",
+            ErrorKind::Other => "其他错误",
+        })
+    }
+}
+
+impl From<io::ErrorKind> for ErrorKind {
+    fn from(kind: io::ErrorKind) -> Self {
+        match kind {
+            io::ErrorKind::PermissionDenied => ErrorKind::PermissionDenied,
+            io::ErrorKind::NotFound => ErrorKind::NotFound,
+            _ => ErrorKind::Other,
+        }
+    }
+}
+
+/// 扫描时无法读取的路径, 容错扫描时记录在映射中
+#[derive(Debug, Clone, PartialEq, Eq, Encode, Decode, Serialize, Deserialize)]
+pub struct ScanError {
+    path: String,
+    kind: ErrorKind,
+    message: String,
+}
+
+impl ScanError {
+    pub(crate) fn new(path: String, kind: ErrorKind, message: String) -> Self {
+        ScanError {
+            path,
+            kind,
+            message,
+        }
+    }
+
+    /// 相对扫描根目录, 与目录的键一致, 以 `/` 分隔,
+    /// 各级名称按 [`File::name`](crate::File::name) 的规则转义
+    pub fn path(&self) -> &str {
+        &self.path
+    }
+
+    pub fn kind(&self) -> ErrorKind {
+        self.kind
+    }
+
+    pub fn message(&self) -> &str {
+        &self.message
+    }
+}
+
+impl fmt::Display for ScanError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}: {} ({})", self.path, self.kind, self.message)
+    }
+}
+
+/// 按类别统计错误数量
+pub fn summarize(errors: &[ScanError]) -> HashMap<ErrorKind, usize> {
+    let mut summary = HashMap::new();
+    for err in errors {
+        *summary.entry(err.kind).or_insert(0) += 1;
+    }
+    summary
+}
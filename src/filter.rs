@@ -0,0 +1,189 @@
+//! 扫描时的路径过滤, 包括 include / exclude 通配符与 `.gitignore` 风格的忽略文件
+
+use std::{
+    fs, io,
+    path::{Path, PathBuf},
+    sync::Arc,
+};
+
+use anyhow::{Context, Result};
+use globset::{GlobBuilder, GlobSet, GlobSetBuilder};
+use ignore::{
+    Match,
+    gitignore::{Gitignore, GitignoreBuilder},
+};
+use parking_lot::Mutex;
+
+use crate::{IgnoreFile, ScanOptions, name};
+
+/// 每个目录中依次读取的忽略文件, 后读取的规则优先
+const IGNORE_FILES: [&str; 3] = [".gitignore", ".ignore", ".dirmapignore"];
+
+/// 一组通配符, 与 `.gitignore` 相同: 不含 `/` 的模式匹配任意层级的名称,
+/// 其余模式匹配相对扫描根目录的路径, 以 `/` 结尾的模式只匹配目录
+struct Patterns {
+    names: GlobSet,
+    paths: GlobSet,
+    /// 与 `names` 中的模式一一对应, 是否只匹配目录
+    names_dir_only: Vec<bool>,
+    paths_dir_only: Vec<bool>,
+}
+
+impl Patterns {
+    fn new(patterns: &[String]) -> Result<Self> {
+        let mut names = GlobSetBuilder::new();
+        let mut paths = GlobSetBuilder::new();
+        let mut names_dir_only = Vec::new();
+        let mut paths_dir_only = Vec::new();
+        for pattern in patterns {
+            let trimmed = pattern.trim_end_matches('/');
+            let dir_only = trimmed.len() != pattern.len();
+            let glob = GlobBuilder::new(trimmed.trim_start_matches('/'))
+                .literal_separator(true)
+                .build()
+                .with_context(|| format!("无效的通配符: {pattern}"))?;
+            if trimmed.contains('/') {
+                paths.add(glob);
+                paths_dir_only.push(dir_only);
+            } else {
+                names.add(glob);
+                names_dir_only.push(dir_only);
+            }
+        }
+        Ok(Patterns {
+            names: names.build()?,
+            paths: paths.build()?,
+            names_dir_only,
+            paths_dir_only,
+        })
+    }
+
+    fn is_match(&self, relative: &Path, is_dir: bool) -> bool {
+        let matches = |set: &GlobSet, dir_only: &[bool], path: &Path| {
+            set.matches(path)
+                .into_iter()
+                .any(|index| is_dir || !dir_only[index])
+        };
+        relative
+            .file_name()
+            .is_some_and(|name| matches(&self.names, &self.names_dir_only, Path::new(name)))
+            || matches(&self.paths, &self.paths_dir_only, relative)
+    }
+}
+
+/// 祖先目录中的忽略规则, 由近及远串联, 子目录共享父目录的链
+#[derive(Clone, Default)]
+pub(crate) struct Ignores(Option<Arc<IgnoreNode>>);
+
+struct IgnoreNode {
+    matcher: Gitignore,
+    parent: Ignores,
+}
+
+/// 扫描任务之间共享的过滤规则
+pub(crate) struct Filter {
+    root: PathBuf,
+    /// 为空时保留所有文件
+    include: Option<Patterns>,
+    exclude: Patterns,
+    ignore_files: bool,
+    /// 读取过的忽略文件, 记录在映射头中
+    loaded: Mutex<Vec<IgnoreFile>>,
+}
+
+impl Filter {
+    pub(crate) fn new(root: &Path, options: &ScanOptions) -> Result<Self> {
+        let include = if options.include.is_empty() {
+            None
+        } else {
+            Some(Patterns::new(&options.include)?)
+        };
+        Ok(Filter {
+            root: root.to_path_buf(),
+            include,
+            exclude: Patterns::new(&options.exclude)?,
+            ignore_files: options.ignore_files,
+            loaded: Mutex::new(Vec::new()),
+        })
+    }
+
+    /// 读取目录中的忽略文件, 返回判断其子路径时使用的规则
+    pub(crate) fn enter_dir(&self, dir: &Path, parent: &Ignores) -> Result<Ignores> {
+        if !self.ignore_files {
+            return Ok(parent.clone());
+        }
+        let mut builder = GitignoreBuilder::new(dir);
+        let mut loaded = Vec::new();
+        for name in IGNORE_FILES {
+            let path = dir.join(name);
+            let content = match fs::read_to_string(&path) {
+                Ok(content) => content,
+                Err(e) if e.kind() == io::ErrorKind::NotFound => continue,
+                Err(e) => {
+                    return Err(e).with_context(|| format!("无法读取忽略文件: {}", path.display()));
+                }
+            };
+            let mut rules = Vec::new();
+            for line in content.lines() {
+                builder
+                    .add_line(Some(path.clone()), line)
+                    .with_context(|| format!("忽略文件规则无效: {}: {line}", path.display()))?;
+                let rule = line.trim_end();
+                if !rule.is_empty() && !rule.starts_with('#') {
+                    rules.push(rule.to_string());
+                }
+            }
+            loaded.push(IgnoreFile {
+                path: name::relative_path(&self.root, &path),
+                rules,
+            });
+        }
+        if loaded.is_empty() {
+            return Ok(parent.clone());
+        }
+        self.loaded.lock().extend(loaded);
+        Ok(Ignores(Some(Arc::new(IgnoreNode {
+            matcher: builder.build()?,
+            parent: parent.clone(),
+        }))))
+    }
+
+    /// 路径是否被过滤, `ignores` 为其父目录的规则; include 只作用于文件
+    pub(crate) fn is_excluded(&self, path: &Path, is_dir: bool, ignores: &Ignores) -> bool {
+        let Ok(relative) = path.strip_prefix(&self.root) else {
+            return false;
+        };
+        if self.exclude.is_match(relative, is_dir) {
+            return true;
+        }
+        if !is_dir
+            && let Some(include) = &self.include
+            && !include.is_match(relative, is_dir)
+        {
+            return true;
+        }
+        ignores.is_ignored(path, is_dir)
+    }
+
+    /// 读取过的忽略文件, 按路径排序
+    pub(crate) fn into_ignore_rules(self) -> Vec<IgnoreFile> {
+        let mut loaded = self.loaded.into_inner();
+        loaded.sort_by(|a, b| a.path.cmp(&b.path));
+        loaded
+    }
+}
+
+impl Ignores {
+    /// 最近的匹配生效, 因此子目录可以用 `!` 重新包含上级目录忽略的路径
+    fn is_ignored(&self, path: &Path, is_dir: bool) -> bool {
+        let mut current = &self.0;
+        while let Some(node) = current {
+            match node.matcher.matched(path, is_dir) {
+                Match::Ignore(_) => return true,
+                Match::Whitelist(_) => return false,
+                Match::None => current = &node.parent.0,
+            }
+        }
+        false
+    }
+}
@@ -0,0 +1,275 @@
+use std::{
+    borrow::Cow,
+    fmt,
+    str::FromStr,
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+use anyhow::{Result, bail};
+use bincode::{Decode, Encode};
+use serde::{Deserialize, Serialize};
+
+use crate::{Category, CustomCategory};
+
+/// 映射文件开头的魔数
+pub const MAGIC: [u8; 4] = *b"DMAP";
+
+/// 当前映射格式版本, 修改 `Header` / `Dir` / `File` 的编码结构时必须递增
+pub const FORMAT_VERSION: u16 = 18;
+
+/// 魔数 + 格式版本, 不参与压缩
+const PREAMBLE_LEN: usize = MAGIC.len() + size_of::<u16>();
+
+/// zstd 帧魔数, 用于识别没有映射头的旧映射文件
+const ZSTD_MAGIC: [u8; 4] = [0x28, 0xB5, 0x2F, 0xFD];
+
+/// 扫描选项, 会完整记录在映射头中
+#[derive(Debug, Clone, Default, PartialEq, Eq, Encode, Decode, Serialize, Deserialize)]
+pub struct ScanOptions {
+    /// 遇到无法读取的路径时记录错误并继续扫描, 否则中止
+    pub tolerant: bool,
+    pub symlinks: SymlinkPolicy,
+    /// 不跨越文件系统边界, 挂载点记录为未展开的目录
+    pub one_file_system: bool,
+    /// 只保留匹配的文件, 为空时保留所有文件, 不影响目录的遍历
+    pub include: Vec<String>,
+    /// 跳过匹配的文件与目录, 目录不再展开
+    pub exclude: Vec<String>,
+    /// 遵循扫描目录中的 `.gitignore`、`.ignore` 与 `.dirmapignore`
+    pub ignore_files: bool,
+    /// 最大扫描深度, 根目录为 0, 达到该深度的目录不再展开
+    pub max_depth: Option<usize>,
+    /// 仍遍历超过最大深度的条目, 将其统计汇总到未展开的目录中
+    pub aggregate: bool,
+    /// 记录文件与目录的时间戳、权限与属主
+    pub metadata: bool,
+    /// 计算文件内容摘要的范围
+    pub hash: HashMode,
+    /// 读取文件开头的字节识别实际类型, 与扩展名表示的类型一并记录
+    pub sniff: bool,
+    /// 解析 PNG、JPEG、WebP、GIF 与 SVG 文件的尺寸、帧数与 EXIF 信息
+    pub images: bool,
+    /// 为 PNG、JPEG、WebP 与 GIF 图片计算感知哈希所用的算法, 为 `None` 时不计算
+    pub perceptual: Option<PerceptualHash>,
+    /// 自定义文件类别, 依次对应从 [`CUSTOM_BASE`](crate::CUSTOM_BASE) 开始的类型编码
+    pub categories: Vec<CustomCategory>,
+}
+
+/// 符号链接的处理方式
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Encode, Decode, Serialize, Deserialize)]
+pub enum SymlinkPolicy {
+    /// 忽略符号链接
+    #[default]
+    Skip,
+    /// 记录为链接条目, 不跟随
+    Record,
+    /// 跟随链接扫描目标, 形成循环或目标不存在的链接记录为链接条目
+    Follow,
+}
+
+impl fmt::Display for SymlinkPolicy {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            SymlinkPolicy::Skip => "skip",
+            SymlinkPolicy::Record => "record",
+            SymlinkPolicy::Follow => "follow",
+        })
+    }
+}
+
+impl FromStr for SymlinkPolicy {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "skip" => Ok(SymlinkPolicy::Skip),
+            "record" => Ok(SymlinkPolicy::Record),
+            "follow" => Ok(SymlinkPolicy::Follow),
+            _ => Err(format!(
+                "未知的符号链接策略: {s}, 可选 skip / record / follow"
+            )),
+        }
+    }
+}
+
+/// 计算文件内容摘要 (BLAKE3) 的范围
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Encode, Decode, Serialize, Deserialize)]
+pub enum HashMode {
+    /// 不计算摘要
+    #[default]
+    None,
+    /// 只计算可能重复的文件, 即存在相同大小的其他非空文件
+    Duplicates,
+    /// 计算所有文件
+    All,
+}
+
+impl fmt::Display for HashMode {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            HashMode::None => "none",
+            HashMode::Duplicates => "duplicates",
+            HashMode::All => "all",
+        })
+    }
+}
+
+impl FromStr for HashMode {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "none" => Ok(HashMode::None),
+            "duplicates" => Ok(HashMode::Duplicates),
+            "all" => Ok(HashMode::All),
+            _ => Err(format!("未知的摘要范围: {s}, 可选 none / duplicates / all")),
+        }
+    }
+}
+
+/// 图片感知哈希的算法, 均生成 64 位哈希, 相似图片的哈希汉明距离较小
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Encode, Decode, Serialize, Deserialize)]
+pub enum PerceptualHash {
+    /// 缩小为 8x8 灰度图, 与平均亮度比较
+    Average,
+    /// 缩小为 9x8 灰度图, 比较相邻像素
+    Difference,
+    /// 缩小为 32x32 灰度图, 取离散余弦变换的低频部分与中位数比较, 最耐压缩与调色
+    Dct,
+}
+
+impl fmt::Display for PerceptualHash {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            PerceptualHash::Average => "ahash",
+            PerceptualHash::Difference => "dhash",
+            PerceptualHash::Dct => "phash",
+        })
+    }
+}
+
+impl FromStr for PerceptualHash {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "ahash" => Ok(PerceptualHash::Average),
+            "dhash" => Ok(PerceptualHash::Difference),
+            "phash" => Ok(PerceptualHash::Dct),
+            _ => Err(format!(
+                "未知的感知哈希算法: {s}, 可选 ahash / dhash / phash"
+            )),
+        }
+    }
+}
+
+/// 映射头, 描述映射的来源
+#[derive(Debug, Clone, PartialEq, Eq, Encode, Decode, Serialize, Deserialize)]
+pub struct Header {
+    pub(crate) root: String,
+    pub(crate) scanned_at: u64,
+    pub(crate) hostname: String,
+    pub(crate) version: String,
+    pub(crate) options: ScanOptions,
+    pub(crate) ignore_rules: Vec<IgnoreFile>,
+}
+
+/// 扫描时读取的忽略文件及其中的规则
+#[derive(Debug, Clone, PartialEq, Eq, Encode, Decode, Serialize, Deserialize)]
+pub struct IgnoreFile {
+    pub(crate) path: String,
+    pub(crate) rules: Vec<String>,
+}
+
+impl IgnoreFile {
+    /// 相对扫描根目录, 与目录的键一致, 以 `/` 分隔,
+    /// 各级名称按 [`File::name`](crate::File::name) 的规则转义
+    pub fn path(&self) -> &str {
+        &self.path
+    }
+
+    /// 去掉空行与注释后的规则, 保持原文
+    pub fn rules(&self) -> &[String] {
+        &self.rules
+    }
+}
+
+impl Header {
+    pub(crate) fn new(root: String, options: ScanOptions) -> Self {
+        Header {
+            root,
+            scanned_at: SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .map(|d| d.as_secs())
+                .unwrap_or_default(),
+            hostname: gethostname::gethostname().to_string_lossy().into_owned(),
+            version: env!("CARGO_PKG_VERSION").to_string(),
+            options,
+            ignore_rules: Vec::new(),
+        }
+    }
+
+    /// 扫描根目录, 映射中的路径都相对于它
+    pub fn root(&self) -> &str {
+        &self.root
+    }
+
+    /// 扫描时间, Unix 时间戳 (秒), 旧映射文件为 0
+    pub fn scanned_at(&self) -> u64 {
+        self.scanned_at
+    }
+
+    pub fn hostname(&self) -> &str {
+        &self.hostname
+    }
+
+    /// 生成映射的 dirmap 版本
+    pub fn version(&self) -> &str {
+        &self.version
+    }
+
+    pub fn options(&self) -> &ScanOptions {
+        &self.options
+    }
+
+    /// 类别的显示名称, 自定义类别取扫描时类别表中的名称
+    pub fn category_name(&self, category: Category) -> Cow<'_, str> {
+        match category {
+            Category::Custom(index) => match self.options.categories.get(index as usize) {
+                Some(custom) => Cow::Borrowed(&custom.name),
+                None => Cow::Owned(category.to_string()),
+            },
+            category => Cow::Owned(category.to_string()),
+        }
+    }
+
+    /// 扫描时生效的忽略文件, 仅在启用 [`ScanOptions::ignore_files`] 时记录
+    pub fn ignore_rules(&self) -> &[IgnoreFile] {
+        &self.ignore_rules
+    }
+}
+
+pub(crate) fn write_preamble(buf: &mut Vec<u8>) {
+    buf.extend_from_slice(&MAGIC);
+    buf.extend_from_slice(&FORMAT_VERSION.to_le_bytes());
+}
+
+/// 校验魔数与格式版本, 返回版本号和剩余的压缩数据, 没有映射头的旧文件视为版本 0
+pub(crate) fn read_preamble(data: &[u8]) -> Result<(u16, &[u8])> {
+    if data.starts_with(&ZSTD_MAGIC) {
+        return Ok((0, data));
+    }
+    if data.len() < PREAMBLE_LEN || !data.starts_with(&MAGIC) {
+        bail!("不是 dirmap 映射文件");
+    }
+    let version = u16::from_le_bytes([data[MAGIC.len()], data[MAGIC.len() + 1]]);
+    if version > FORMAT_VERSION {
+        bail!("映射格式版本 {version} 高于当前支持的版本 {FORMAT_VERSION}, 请升级 dirmap");
+    }
+    Ok((version, &data[PREAMBLE_LEN..]))
+}
+
+/// 读取映射文件的格式版本
+pub fn format_version(data: &[u8]) -> Result<u16> {
+    read_preamble(data).map(|(version, _)| version)
+}
@@ -0,0 +1,527 @@
+//! 图片的尺寸、帧数与 EXIF 信息
+//!
+//! 只解析文件头与块结构, 不解码像素. 尺寸由 imagesize 读取, 帧数按各格式的块结构统计,
+//! SVG 取根元素的 `width` / `height`, 缺失时取 `viewBox`
+
+use std::{
+    fs,
+    io::{self, BufReader, Cursor, Read, Seek, SeekFrom},
+    path::Path,
+};
+
+use bincode::{Decode, Encode};
+use serde::{Deserialize, Serialize};
+
+use crate::FileKind;
+
+/// 图片信息, 扫描时启用 [`ScanOptions::images`](crate::ScanOptions::images) 才会记录
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Encode, Decode, Serialize, Deserialize)]
+pub struct ImageInfo {
+    pub width: u32,
+    pub height: u32,
+    /// 帧数, 静态图片为 1
+    pub frames: u32,
+    /// EXIF 拍摄时间, EXIF 不含时区, 按 UTC 换算为 Unix 时间戳
+    pub taken_at: Option<i64>,
+    /// EXIF 方向, 1 - 8
+    pub orientation: Option<u16>,
+}
+
+impl ImageInfo {
+    pub fn is_animated(&self) -> bool {
+        self.frames > 1
+    }
+
+    /// 按 EXIF 方向旋转后的显示尺寸
+    pub fn display_size(&self) -> (u32, u32) {
+        match self.orientation {
+            Some(5..=8) => (self.height, self.width),
+            _ => (self.width, self.height),
+        }
+    }
+}
+
+/// 是否为可以解析的图片类型
+pub(crate) fn is_supported(kind: FileKind) -> bool {
+    matches!(
+        kind,
+        FileKind::Png | FileKind::Jpeg | FileKind::Webp | FileKind::Gif | FileKind::Svg
+    )
+}
+
+/// SVG 只读取开头的部分, 根元素通常位于其中
+const SVG_PREFIX: u64 = 64 * 1024;
+
+/// 动画 WebP 帧与图像块保留的开头字节数, 足以容纳尺寸等头部字段
+const WEBP_CHUNK_PREFIX: u32 = 32;
+
+/// 读取 [`parse`] 所需的部分: 按块结构跳过像素数据, 拼接文件头、元数据块与各块开头
+///
+/// GIF 的图像块由不定长的子块组成, 统计帧数需要读取整个文件. 文件截断时返回已读取的部分
+pub(crate) fn read_header(path: &Path, kind: FileKind) -> io::Result<Vec<u8>> {
+    let mut reader = BufReader::new(fs::File::open(path)?);
+    let mut out = Vec::new();
+    match kind {
+        FileKind::Png => png_header(&mut reader, &mut out)?,
+        FileKind::Jpeg => jpeg_header(&mut reader, &mut out)?,
+        FileKind::Webp => webp_header(&mut reader, &mut out)?,
+        FileKind::Svg => {
+            reader.take(SVG_PREFIX).read_to_end(&mut out)?;
+        }
+        _ => {
+            reader.read_to_end(&mut out)?;
+        }
+    }
+    Ok(out)
+}
+
+/// 追加 `len` 字节, 文件提前结束时返回 `false`
+fn copy(reader: &mut impl Read, out: &mut Vec<u8>, len: u64) -> io::Result<bool> {
+    Ok(reader.take(len).read_to_end(out)? as u64 == len)
+}
+
+fn read_byte(reader: &mut impl Read) -> io::Result<Option<u8>> {
+    let mut byte = 0;
+    let read = reader.read(std::slice::from_mut(&mut byte))?;
+    Ok((read == 1).then_some(byte))
+}
+
+fn skip(reader: &mut impl Seek, len: u64) -> io::Result<()> {
+    reader.seek(SeekFrom::Current(len as i64)).map(drop)
+}
+
+/// 去掉 `IDAT` 与 APNG 的 `fdAT` 块
+fn png_header(reader: &mut (impl Read + Seek), out: &mut Vec<u8>) -> io::Result<()> {
+    if !copy(reader, out, 8)? {
+        return Ok(());
+    }
+    while copy(reader, out, 8)? {
+        let header = &out[out.len() - 8..];
+        let len = u32::from_be_bytes([header[0], header[1], header[2], header[3]]) as u64;
+        let typ: [u8; 4] = [header[4], header[5], header[6], header[7]];
+        // 块数据之后是 4 字节的 CRC
+        if matches!(&typ, b"IDAT" | b"fdAT") {
+            out.truncate(out.len() - 8);
+            skip(reader, len + 4)?;
+        } else if !copy(reader, out, len + 4)? || &typ == b"IEND" {
+            break;
+        }
+    }
+    Ok(())
+}
+
+/// 复制扫描数据之前的所有段, 之后补上结束标记
+fn jpeg_header(reader: &mut impl Read, out: &mut Vec<u8>) -> io::Result<()> {
+    if !copy(reader, out, 2)? {
+        return Ok(());
+    }
+    loop {
+        if read_byte(reader)? != Some(0xFF) {
+            return Ok(());
+        }
+        // 标记前可以有填充的 0xFF
+        let marker = loop {
+            match read_byte(reader)? {
+                Some(0xFF) => continue,
+                Some(marker) => break marker,
+                None => return Ok(()),
+            }
+        };
+        match marker {
+            0xD9 | 0xDA => break,
+            // 没有长度字段的标记
+            0x01 | 0xD0..=0xD8 => out.extend_from_slice(&[0xFF, marker]),
+            _ => {
+                out.extend_from_slice(&[0xFF, marker]);
+                if !copy(reader, out, 2)? {
+                    return Ok(());
+                }
+                let len = u16::from_be_bytes([out[out.len() - 2], out[out.len() - 1]]) as u64;
+                if !copy(reader, out, len.saturating_sub(2))? {
+                    return Ok(());
+                }
+            }
+        }
+    }
+    out.extend_from_slice(b"\xff\xd9");
+    Ok(())
+}
+
+/// 图像块与动画帧只保留开头, 改写块长度与 RIFF 长度使其保持一致
+fn webp_header(reader: &mut (impl Read + Seek), out: &mut Vec<u8>) -> io::Result<()> {
+    if !copy(reader, out, 12)? {
+        return Ok(());
+    }
+    while copy(reader, out, 8)? {
+        let start = out.len() - 8;
+        let header = &out[start..];
+        let typ: [u8; 4] = [header[0], header[1], header[2], header[3]];
+        let len = u32::from_le_bytes([header[4], header[5], header[6], header[7]]);
+        let padded = len as u64 + len as u64 % 2;
+        if matches!(&typ, b"VP8 " | b"VP8L" | b"ALPH" | b"ANMF") && len > WEBP_CHUNK_PREFIX {
+            out[start + 4..start + 8].copy_from_slice(&WEBP_CHUNK_PREFIX.to_le_bytes());
+            if !copy(reader, out, WEBP_CHUNK_PREFIX as u64)? {
+                break;
+            }
+            skip(reader, padded - WEBP_CHUNK_PREFIX as u64)?;
+        } else if !copy(reader, out, padded)? {
+            break;
+        }
+    }
+    let riff = (out.len() as u32).saturating_sub(8);
+    out[4..8].copy_from_slice(&riff.to_le_bytes());
+    Ok(())
+}
+
+/// 解析图片信息, 无法解析时为 `None`
+pub(crate) fn parse(data: &[u8], kind: FileKind) -> Option<ImageInfo> {
+    if kind == FileKind::Svg {
+        let (width, height) = svg_size(data)?;
+        return Some(ImageInfo {
+            width,
+            height,
+            frames: 1,
+            ..Default::default()
+        });
+    }
+
+    let size = imagesize::blob_size(data).ok()?;
+    let frames = match kind {
+        FileKind::Png => png_frames(data),
+        FileKind::Gif => gif_frames(data),
+        FileKind::Webp => webp_frames(data),
+        _ => 1,
+    };
+    let (taken_at, orientation) = exif_fields(data);
+    Some(ImageInfo {
+        width: size.width.try_into().ok()?,
+        height: size.height.try_into().ok()?,
+        frames,
+        taken_at,
+        orientation,
+    })
+}
+
+/// APNG 在 `acTL` 块中记录帧数, 该块必须位于图像数据之前
+fn png_frames(data: &[u8]) -> u32 {
+    let mut pos = 8;
+    while let Some(header) = data.get(pos..pos + 8) {
+        let len = u32::from_be_bytes([header[0], header[1], header[2], header[3]]) as usize;
+        match &header[4..8] {
+            b"acTL" => {
+                return data
+                    .get(pos + 8..pos + 12)
+                    .map_or(1, |n| u32::from_be_bytes([n[0], n[1], n[2], n[3]]).max(1));
+            }
+            b"IDAT" | b"IEND" => break,
+            _ => pos += 12 + len,
+        }
+    }
+    1
+}
+
+/// 逐个跳过扩展块与图像块, 统计图像描述符的数量
+fn gif_frames(data: &[u8]) -> u32 {
+    fn color_table(flags: u8) -> usize {
+        if flags & 0x80 != 0 {
+            3 << ((flags & 0x07) + 1)
+        } else {
+            0
+        }
+    }
+
+    fn skip_sub_blocks(data: &[u8], mut pos: usize) -> Option<usize> {
+        loop {
+            let len = *data.get(pos)? as usize;
+            pos += 1 + len;
+            if len == 0 {
+                return Some(pos);
+            }
+        }
+    }
+
+    let Some(&flags) = data.get(10) else {
+        return 1;
+    };
+    let mut pos = 13 + color_table(flags);
+    let mut frames = 0;
+    loop {
+        let next = match data.get(pos) {
+            Some(0x21) => skip_sub_blocks(data, pos + 2),
+            Some(0x2C) => {
+                frames += 1;
+                data.get(pos + 9)
+                    .and_then(|&flags| skip_sub_blocks(data, pos + 11 + color_table(flags)))
+            }
+            _ => None,
+        };
+        match next {
+            Some(next) => pos = next,
+            None => break,
+        }
+    }
+    frames.max(1)
+}
+
+/// 动画 WebP 的每一帧是一个 `ANMF` 块
+fn webp_frames(data: &[u8]) -> u32 {
+    let mut pos = 12;
+    let mut frames = 0;
+    while let Some(header) = data.get(pos..pos + 8) {
+        if &header[..4] == b"ANMF" {
+            frames += 1;
+        }
+        let len = u32::from_le_bytes([header[4], header[5], header[6], header[7]]) as usize;
+        pos += 8 + len + len % 2;
+    }
+    frames.max(1)
+}
+
+fn exif_fields(data: &[u8]) -> (Option<i64>, Option<u16>) {
+    let Ok(exif) = exif::Reader::new().read_from_container(&mut Cursor::new(data)) else {
+        return (None, None);
+    };
+    let taken_at = [exif::Tag::DateTimeOriginal, exif::Tag::DateTime]
+        .into_iter()
+        .find_map(|tag| match &exif.get_field(tag, exif::In::PRIMARY)?.value {
+            exif::Value::Ascii(values) => {
+                let time = exif::DateTime::from_ascii(values.first()?).ok()?;
+                Some(timestamp(&time))
+            }
+            _ => None,
+        });
+    let orientation = exif
+        .get_field(exif::Tag::Orientation, exif::In::PRIMARY)
+        .and_then(|field| field.value.get_uint(0))
+        .and_then(|value| u16::try_from(value).ok());
+    (taken_at, orientation)
+}
+
+/// 公历日期换算为 Unix 时间戳
+fn timestamp(time: &exif::DateTime) -> i64 {
+    let (month, day) = (time.month as i64, time.day as i64);
+    let year = time.year as i64 - (month <= 2) as i64;
+    let era = year.div_euclid(400);
+    let year_of_era = year - era * 400;
+    let day_of_year = (153 * (month + if month > 2 { -3 } else { 9 }) + 2) / 5 + day - 1;
+    let day_of_era = year_of_era * 365 + year_of_era / 4 - year_of_era / 100 + day_of_year;
+    let days = era * 146_097 + day_of_era - 719_468;
+    days * 86_400 + time.hour as i64 * 3600 + time.minute as i64 * 60 + time.second as i64
+}
+
+/// 根元素 `<svg>` 的尺寸, 只接受无单位或 `px` 的长度
+fn svg_size(data: &[u8]) -> Option<(u32, u32)> {
+    let text = String::from_utf8_lossy(data);
+    let start = text.find("<svg")?;
+    let tag = &text[start..start + text[start..].find('>')?];
+
+    let attr = |name: &str| {
+        let mut rest = tag;
+        loop {
+            let pos = rest.find(name)?;
+            let before = rest[..pos].chars().next_back();
+            rest = &rest[pos + name.len()..];
+            let Some(value) = rest.trim_start().strip_prefix('=') else {
+                continue;
+            };
+            if !before.is_some_and(char::is_whitespace) {
+                continue;
+            }
+            // 只接受带引号的属性值, 不带引号时无法确定结尾
+            let value = value.trim_start();
+            let quote = value.chars().next().filter(|c| matches!(c, '"' | '\''))?;
+            let value = &value[quote.len_utf8()..];
+            return Some(&value[..value.find(quote)?]);
+        }
+    };
+    let length = |value: &str| {
+        let value = value.trim();
+        value
+            .strip_suffix("px")
+            .unwrap_or(value)
+            .parse::<f64>()
+            .ok()
+            .filter(|length| *length > 0.0)
+            .map(|length| length.round() as u32)
+    };
+
+    if let (Some(width), Some(height)) = (
+        attr("width").and_then(length),
+        attr("height").and_then(length),
+    ) {
+        return Some((width, height));
+    }
+    let view_box: Vec<_> = attr("viewBox")?
+        .split([' ', ','])
+        .filter(|part| !part.is_empty())
+        .collect();
+    match view_box[..] {
+        [_, _, width, height] => Some((length(width)?, length(height)?)),
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+
+    use super::*;
+
+    fn chunk(typ: &[u8], data: &[u8]) -> Vec<u8> {
+        let mut chunk = (data.len() as u32).to_be_bytes().to_vec();
+        chunk.extend_from_slice(typ);
+        chunk.extend_from_slice(data);
+        chunk.extend_from_slice(&[0; 4]);
+        chunk
+    }
+
+    fn png(width: u32, height: u32, frames: Option<u32>) -> Vec<u8> {
+        let mut ihdr = [width.to_be_bytes(), height.to_be_bytes()].concat();
+        ihdr.extend_from_slice(&[8, 6, 0, 0, 0]);
+        let mut data = b"\x89PNG\r\n\x1a\n".to_vec();
+        data.extend(chunk(b"IHDR", &ihdr));
+        if let Some(frames) = frames {
+            data.extend(chunk(b"acTL", &[frames.to_be_bytes(), [0; 4]].concat()));
+        }
+        data.extend(chunk(b"IDAT", &[]));
+        data.extend(chunk(b"IEND", &[]));
+        data
+    }
+
+    #[test]
+    fn test_frames() {
+        let info = parse(&png(4200, 2800, None), FileKind::Png).expect("解析失败");
+        assert_eq!((info.width, info.height, info.frames), (4200, 2800, 1));
+        let info = parse(&png(16, 16, Some(12)), FileKind::Png).expect("解析失败");
+        assert!(info.is_animated());
+        assert_eq!(info.frames, 12);
+
+        // 带全局颜色表的 GIF, 两帧各有一个图形控制扩展
+        let mut gif = b"GIF89a\x0a\x00\x05\x00\x80\x00\x00".to_vec();
+        gif.extend_from_slice(&[0; 6]);
+        for _ in 0..2 {
+            gif.extend_from_slice(b"\x21\xf9\x04\x00\x0a\x00\x00\x00");
+            gif.extend_from_slice(b"\x2c\x00\x00\x00\x00\x0a\x00\x05\x00\x00\x02\x02\x4c\x01\x00");
+        }
+        gif.push(0x3b);
+        let info = parse(&gif, FileKind::Gif).expect("解析失败");
+        assert_eq!((info.width, info.height, info.frames), (10, 5, 2));
+
+        assert!(parse(b"not an image", FileKind::Png).is_none());
+    }
+
+    #[test]
+    fn test_exif() {
+        use exif::{Field, In, Tag, Value, experimental::Writer};
+
+        let date = Field {
+            tag: Tag::DateTimeOriginal,
+            ifd_num: In::PRIMARY,
+            value: Value::Ascii(vec![b"2021:06:15 08:30:00".to_vec()]),
+        };
+        let orientation = Field {
+            tag: Tag::Orientation,
+            ifd_num: In::PRIMARY,
+            value: Value::Short(vec![6]),
+        };
+        let mut writer = Writer::new();
+        writer.push_field(&date);
+        writer.push_field(&orientation);
+        let mut tiff = Cursor::new(Vec::new());
+        writer.write(&mut tiff, false).expect("写入 EXIF 失败");
+        let tiff = tiff.into_inner();
+
+        let mut jpeg = b"\xff\xd8\xff\xe1".to_vec();
+        jpeg.extend_from_slice(&(tiff.len() as u16 + 8).to_be_bytes());
+        jpeg.extend_from_slice(b"Exif\0\0");
+        jpeg.extend_from_slice(&tiff);
+        // 基线 SOF0: 精度 8, 高 3000, 宽 4000, 3 个分量
+        jpeg.extend_from_slice(b"\xff\xc0\x00\x11\x08\x0b\xb8\x0f\xa0\x03");
+        jpeg.extend_from_slice(&[1, 0x22, 0, 2, 0x11, 1, 3, 0x11, 1]);
+        jpeg.extend_from_slice(b"\xff\xd9");
+
+        let info = parse(&jpeg, FileKind::Jpeg).expect("解析失败");
+        assert_eq!((info.width, info.height), (4000, 3000));
+        assert_eq!(info.taken_at, Some(1_623_745_800));
+        assert_eq!(info.orientation, Some(6));
+        assert_eq!(info.display_size(), (3000, 4000));
+
+        let tmp = tempfile::NamedTempFile::new().expect("创建临时文件失败");
+        fs::write(tmp.path(), &jpeg).expect("写入文件失败");
+        let header = read_header(tmp.path(), FileKind::Jpeg).expect("读取文件头失败");
+        assert_eq!(parse(&header, FileKind::Jpeg), Some(info));
+    }
+
+    #[test]
+    fn test_read_header() {
+        use image::{ImageFormat, RgbImage};
+
+        let tmp = tempfile::tempdir().expect("创建临时目录失败");
+        // 噪声图片的像素数据远大于文件头
+        let noise = RgbImage::from_fn(256, 128, |x, y| {
+            let level = (x.wrapping_mul(2_654_435_761) ^ y.wrapping_mul(40_503))
+                .wrapping_mul(2_246_822_519)
+                >> 24;
+            image::Rgb([level as u8, (level >> 1) as u8, !level as u8])
+        });
+        for (name, format, kind) in [
+            ("a.png", ImageFormat::Png, FileKind::Png),
+            ("a.jpg", ImageFormat::Jpeg, FileKind::Jpeg),
+            ("a.webp", ImageFormat::WebP, FileKind::Webp),
+        ] {
+            let path = tmp.path().join(name);
+            noise.save_with_format(&path, format).expect("写入图片失败");
+            let data = fs::read(&path).expect("读取文件失败");
+            let header = read_header(&path, kind).expect("读取文件头失败");
+            assert!(header.len() < 1024 && data.len() > 16 * 1024, "{name}");
+            let info = parse(&header, kind).expect("解析失败");
+            assert_eq!(Some(info), parse(&data, kind), "{name}");
+            assert_eq!((info.width, info.height), (256, 128));
+        }
+    }
+
+    #[test]
+    fn test_svg() {
+        let svg = br#"<?xml version="1.0"?><svg xmlns="http://www.w3.org/2000/svg" stroke-width="3" width="120" height="80px">"#;
+        let info = parse(svg, FileKind::Svg).expect("解析失败");
+        assert_eq!((info.width, info.height), (120, 80));
+        let svg = br#"<svg viewBox="0 0 24.5 12" width="100%">"#;
+        let info = parse(svg, FileKind::Svg).expect("解析失败");
+        assert_eq!((info.width, info.height), (25, 12));
+        let svg = "<svg width=中 height=\"3\">".as_bytes();
+        assert!(parse(svg, FileKind::Svg).is_none());
+        assert!(parse(b"<svg width=\xFE height='3'>", FileKind::Svg).is_none());
+    }
+
+    #[test]
+    fn test_scan_images() {
+        let tmp = tempfile::tempdir().expect("创建临时目录失败");
+        fs::write(tmp.path().join("big.png"), png(5000, 3000, None)).expect("写入文件失败");
+        fs::write(tmp.path().join("renamed.dat"), png(10, 10, None)).expect("写入文件失败");
+        fs::write(tmp.path().join("notes.txt"), "hello").expect("写入文件失败");
+
+        let start = tmp.path().to_str().expect("路径无效");
+        let options = crate::ScanOptions {
+            images: true,
+            sniff: true,
+            ..Default::default()
+        };
+        let dirs = crate::scan(start, options).expect("扫描失败");
+        let dirs = crate::unmap(&dirs.encode().expect("编码失败")).expect("解映射失败");
+        let large: Vec<_> = dirs
+            .root()
+            .files()
+            .filter(|file| file.image().is_some_and(|image| image.width > 4000))
+            .map(|file| file.name())
+            .collect();
+        assert_eq!(large, ["big.png"]);
+        // 按内容识别为 PNG 的文件同样解析
+        let renamed = dirs.file("renamed.dat").and_then(|file| file.image());
+        assert_eq!(renamed.map(|image| image.width), Some(10));
+        assert!(
+            dirs.file("notes.txt")
+                .and_then(|file| file.image())
+                .is_none()
+        );
+    }
+}
@@ -0,0 +1,425 @@
+//! 文件类型与分类
+//!
+//! 文件类型以 `u8` 编码存储在映射中. 内置类型的编码固定不变, 新类型只能追加;
+//! 自定义类别的编码从 [`CUSTOM_BASE`] 开始, 依次对应扫描选项中
+//! [`ScanOptions::categories`](crate::ScanOptions::categories) 的各项
+
+use std::{
+    collections::HashMap,
+    fmt, fs,
+    io::{self, Read},
+    path::Path,
+    str::FromStr,
+};
+
+use anyhow::{Result, bail};
+use bincode::{Decode, Encode};
+use serde::{Deserialize, Serialize};
+
+/// 自定义类别的起始编码, 之前的编码留给内置类型
+pub const CUSTOM_BASE: u8 = 64;
+
+/// 识别内容时读取的字节数, 足以覆盖 Office 文档等需要查看压缩包目录的格式
+const SNIFF_LEN: u64 = 8 * 1024;
+
+/// 文件类型, 与 [`File`](crate::File) 中存储的 `u8` 编码一一对应
+///
+/// | 编码 | 类型 |
+/// | --- | --- |
+/// | 0 - 4 | PNG, JPEG, WebP, SVG, GIF |
+/// | 5 | 其他 |
+/// | 6 - 12 | BMP, TIFF, ICO, HEIF, AVIF, 相机原始格式, 其他图片 |
+/// | 13 - 18 | 视频, 音频, 压缩包, 文档, 源代码, 可执行文件与库 |
+/// | 64 - 255 | 自定义类别 |
+///
+/// 未分配的内置编码解码为 [`FileKind::Other`]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum FileKind {
+    Png,
+    Jpeg,
+    Webp,
+    Svg,
+    Gif,
+    Other,
+    Bmp,
+    Tiff,
+    Ico,
+    Heif,
+    Avif,
+    /// 相机原始格式, 如 CR2、NEF、DNG
+    RawImage,
+    /// 其他图片格式, 如 PSD、TGA
+    Image,
+    Video,
+    Audio,
+    Archive,
+    Document,
+    Source,
+    /// 可执行文件、动态库与目标文件
+    Binary,
+    /// 自定义类别, 值为类别表中的下标
+    Custom(u8),
+}
+
+/// 文件类型所属的大类
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub enum Category {
+    Image,
+    Video,
+    Audio,
+    Archive,
+    Document,
+    Source,
+    Binary,
+    Other,
+    /// 自定义类别, 值为类别表中的下标
+    Custom(u8),
+}
+
+impl FileKind {
+    /// 内置类型, 下标即编码
+    const BUILTIN: [FileKind; 19] = [
+        FileKind::Png,
+        FileKind::Jpeg,
+        FileKind::Webp,
+        FileKind::Svg,
+        FileKind::Gif,
+        FileKind::Other,
+        FileKind::Bmp,
+        FileKind::Tiff,
+        FileKind::Ico,
+        FileKind::Heif,
+        FileKind::Avif,
+        FileKind::RawImage,
+        FileKind::Image,
+        FileKind::Video,
+        FileKind::Audio,
+        FileKind::Archive,
+        FileKind::Document,
+        FileKind::Source,
+        FileKind::Binary,
+    ];
+
+    pub fn from_code(code: u8) -> Self {
+        if code >= CUSTOM_BASE {
+            return FileKind::Custom(code - CUSTOM_BASE);
+        }
+        Self::BUILTIN
+            .get(code as usize)
+            .copied()
+            .unwrap_or(FileKind::Other)
+    }
+
+    pub fn code(self) -> u8 {
+        match self {
+            FileKind::Custom(index) => CUSTOM_BASE.saturating_add(index),
+            kind => Self::BUILTIN
+                .iter()
+                .position(|&builtin| builtin == kind)
+                .expect("内置类型必须在编码表中") as u8,
+        }
+    }
+
+    pub fn category(self) -> Category {
+        match self {
+            FileKind::Png
+            | FileKind::Jpeg
+            | FileKind::Webp
+            | FileKind::Svg
+            | FileKind::Gif
+            | FileKind::Bmp
+            | FileKind::Tiff
+            | FileKind::Ico
+            | FileKind::Heif
+            | FileKind::Avif
+            | FileKind::RawImage
+            | FileKind::Image => Category::Image,
+            FileKind::Video => Category::Video,
+            FileKind::Audio => Category::Audio,
+            FileKind::Archive => Category::Archive,
+            FileKind::Document => Category::Document,
+            FileKind::Source => Category::Source,
+            FileKind::Binary => Category::Binary,
+            FileKind::Other => Category::Other,
+            FileKind::Custom(index) => Category::Custom(index),
+        }
+    }
+
+    pub fn is_image(self) -> bool {
+        self.category() == Category::Image
+    }
+
+    /// 按扩展名识别内置类型, 不区分大小写
+    pub fn from_extension(ext: &str) -> Self {
+        match ext.to_ascii_lowercase().as_str() {
+            "png" => FileKind::Png,
+            "jpg" | "jpeg" | "jpe" | "jfif" => FileKind::Jpeg,
+            "webp" => FileKind::Webp,
+            "svg" | "svgz" => FileKind::Svg,
+            "gif" => FileKind::Gif,
+            "bmp" | "dib" => FileKind::Bmp,
+            "tif" | "tiff" => FileKind::Tiff,
+            "ico" | "cur" => FileKind::Ico,
+            "heic" | "heif" => FileKind::Heif,
+            "avif" => FileKind::Avif,
+            "cr2" | "cr3" | "nef" | "arw" | "dng" | "orf" | "rw2" | "raf" | "pef" | "srw" => {
+                FileKind::RawImage
+            }
+            "psd" | "tga" | "xcf" | "jxl" | "jp2" | "exr" | "hdr" | "pcx" | "ppm" | "pgm"
+            | "pbm" | "qoi" => FileKind::Image,
+            "mp4" | "m4v" | "mkv" | "webm" | "avi" | "mov" | "wmv" | "flv" | "mpg" | "mpeg"
+            | "m2ts" | "mts" | "3gp" | "ogv" | "vob" => FileKind::Video,
+            "mp3" | "flac" | "wav" | "ogg" | "oga" | "opus" | "m4a" | "aac" | "wma" | "aif"
+            | "aiff" | "mid" | "midi" | "ape" | "alac" => FileKind::Audio,
+            "zip" | "tar" | "gz" | "tgz" | "bz2" | "tbz2" | "xz" | "txz" | "zst" | "7z" | "rar"
+            | "lz4" | "lzma" | "cab" | "iso" | "dmg" | "deb" | "rpm" | "jar" | "apk" | "whl" => {
+                FileKind::Archive
+            }
+            "pdf" | "doc" | "docx" | "xls" | "xlsx" | "ppt" | "pptx" | "odt" | "ods" | "odp"
+            | "rtf" | "txt" | "md" | "markdown" | "rst" | "epub" | "csv" | "tex" => {
+                FileKind::Document
+            }
+            "rs" | "c" | "h" | "cc" | "cpp" | "cxx" | "hpp" | "hh" | "py" | "js" | "mjs"
+            | "cjs" | "ts" | "tsx" | "jsx" | "java" | "kt" | "kts" | "go" | "rb" | "php"
+            | "swift" | "cs" | "scala" | "sh" | "bash" | "zsh" | "fish" | "ps1" | "lua" | "pl"
+            | "html" | "htm" | "css" | "scss" | "sass" | "less" | "vue" | "json" | "toml"
+            | "yaml" | "yml" | "xml" | "sql" | "hs" | "ml" | "ex" | "exs" | "erl" | "clj"
+            | "dart" | "zig" | "nim" | "asm" | "s" => FileKind::Source,
+            "exe" | "dll" | "so" | "dylib" | "a" | "lib" | "o" | "obj" | "bin" | "class"
+            | "wasm" | "pyc" | "sys" | "msi" => FileKind::Binary,
+            _ => FileKind::Other,
+        }
+    }
+
+    /// 按文件名的扩展名识别内置类型
+    pub fn from_name(name: &str) -> Self {
+        Path::new(name)
+            .extension()
+            .and_then(|ext| ext.to_str())
+            .map_or(FileKind::Other, FileKind::from_extension)
+    }
+}
+
+impl fmt::Display for FileKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            FileKind::Png => f.write_str("PNG"),
+            FileKind::Jpeg => f.write_str("JPEG"),
+            FileKind::Webp => f.write_str("WebP"),
+            FileKind::Svg => f.write_str("SVG"),
+            FileKind::Gif => f.write_str("GIF"),
+            FileKind::Bmp => f.write_str("BMP"),
+            FileKind::Tiff => f.write_str("TIFF"),
+            FileKind::Ico => f.write_str("ICO"),
+            FileKind::Heif => f.write_str("HEIF"),
+            FileKind::Avif => f.write_str("AVIF"),
+            FileKind::RawImage => f.write_str("相机原始格式"),
+            kind => kind.category().fmt(f),
+        }
+    }
+}
+
+impl fmt::Display for Category {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Category::Image => f.write_str("图片"),
+            Category::Video => f.write_str("视频"),
+            Category::Audio => f.write_str("音频"),
+            Category::Archive => f.write_str("压缩包"),
+            Category::Document => f.write_str("文档"),
+            Category::Source => f.write_str("源代码"),
+            Category::Binary => f.write_str("二进制"),
+            Category::Other => f.write_str("其他"),
+            Category::Custom(index) => write!(f, "自定义类别 #{index}"),
+        }
+    }
+}
+
+/// 自定义类别, 扩展名匹配的文件归入该类别, 优先于内置类型
+#[derive(Debug, Clone, PartialEq, Eq, Encode, Decode, Serialize, Deserialize)]
+pub struct CustomCategory {
+    pub name: String,
+    /// 不含 `.` 的扩展名, 不区分大小写
+    pub extensions: Vec<String>,
+}
+
+impl FromStr for CustomCategory {
+    type Err = String;
+
+    /// 形如 `name=ext1,ext2`
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let Some((name, extensions)) = s.split_once('=') else {
+            return Err(format!("自定义类别格式应为 名称=扩展名,扩展名: {s}"));
+        };
+        let extensions: Vec<_> = extensions
+            .split(',')
+            .map(|ext| ext.trim().trim_start_matches('.').to_string())
+            .filter(|ext| !ext.is_empty())
+            .collect();
+        if name.trim().is_empty() || extensions.is_empty() {
+            return Err(format!("自定义类别的名称与扩展名不能为空: {s}"));
+        }
+        Ok(CustomCategory {
+            name: name.trim().to_string(),
+            extensions,
+        })
+    }
+}
+
+/// 扫描时使用的分类器: 先查自定义类别, 再按内置表识别
+#[derive(Debug, Default)]
+pub(crate) struct Classifier {
+    custom: HashMap<String, u8>,
+}
+
+impl Classifier {
+    pub(crate) fn new(categories: &[CustomCategory]) -> Result<Self> {
+        let limit = (u8::MAX - CUSTOM_BASE) as usize + 1;
+        if categories.len() > limit {
+            bail!("自定义类别过多: {} 个, 最多 {limit} 个", categories.len());
+        }
+        let mut custom = HashMap::new();
+        for (index, category) in categories.iter().enumerate() {
+            for ext in &category.extensions {
+                // 同一扩展名出现在多个类别中时以先出现的为准
+                custom
+                    .entry(ext.to_ascii_lowercase())
+                    .or_insert(index as u8);
+            }
+        }
+        Ok(Classifier { custom })
+    }
+
+    pub(crate) fn classify(&self, name: &str) -> FileKind {
+        let Some(ext) = Path::new(name).extension().and_then(|ext| ext.to_str()) else {
+            return FileKind::Other;
+        };
+        match self.custom.get(&ext.to_ascii_lowercase()) {
+            Some(&index) => FileKind::Custom(index),
+            None => FileKind::from_extension(ext),
+        }
+    }
+}
+
+/// 读取文件开头的字节识别内置类型, 无法识别时为 [`FileKind::Other`]
+pub(crate) fn sniff(path: &Path) -> io::Result<FileKind> {
+    let mut buf = Vec::with_capacity(SNIFF_LEN as usize);
+    fs::File::open(path)?
+        .take(SNIFF_LEN)
+        .read_to_end(&mut buf)?;
+    Ok(sniff_bytes(&buf))
+}
+
+fn sniff_bytes(buf: &[u8]) -> FileKind {
+    let Some(typ) = infer::get(buf) else {
+        return FileKind::Other;
+    };
+    match FileKind::from_extension(typ.extension()) {
+        FileKind::Other => match typ.matcher_type() {
+            infer::MatcherType::App => FileKind::Binary,
+            infer::MatcherType::Archive => FileKind::Archive,
+            infer::MatcherType::Audio => FileKind::Audio,
+            infer::MatcherType::Book | infer::MatcherType::Doc => FileKind::Document,
+            infer::MatcherType::Image => FileKind::Image,
+            infer::MatcherType::Video => FileKind::Video,
+            _ => FileKind::Other,
+        },
+        kind => kind,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_codes() {
+        for code in 0..=u8::MAX {
+            let kind = FileKind::from_code(code);
+            if code < FileKind::BUILTIN.len() as u8 || code >= CUSTOM_BASE {
+                assert_eq!(kind.code(), code);
+            } else {
+                assert_eq!(kind, FileKind::Other);
+            }
+        }
+        // 旧映射中的编码含义不变
+        assert_eq!(FileKind::Png.code(), 0);
+        assert_eq!(FileKind::Gif.code(), 4);
+        assert_eq!(FileKind::Other.code(), 5);
+    }
+
+    #[test]
+    fn test_classifier() {
+        let categories = [
+            "design=psd,sketch"
+                .parse::<CustomCategory>()
+                .expect("解析失败"),
+            "data=.parquet, JSON".parse().expect("解析失败"),
+        ];
+        let classifier = Classifier::new(&categories).expect("创建分类器失败");
+        assert_eq!(classifier.classify("a.PSD"), FileKind::Custom(0));
+        assert_eq!(classifier.classify("b.json"), FileKind::Custom(1));
+        assert_eq!(classifier.classify("c.mkv").category(), Category::Video);
+        assert_eq!(classifier.classify("lib.rs").category(), Category::Source);
+        assert_eq!(classifier.classify("Makefile"), FileKind::Other);
+        assert!(classifier.classify("x.HEIC").is_image());
+        assert!("design".parse::<CustomCategory>().is_err());
+        assert!("design=".parse::<CustomCategory>().is_err());
+    }
+
+    #[test]
+    fn test_custom_scan() {
+        let options = crate::ScanOptions {
+            categories: vec!["rust=rs".parse().expect("解析失败")],
+            ..Default::default()
+        };
+        let dirs = crate::scan("src", options).expect("扫描失败");
+        let dirs = crate::unmap(&dirs.encode().expect("编码失败")).expect("解映射失败");
+        let kind = dirs.file("lib.rs").expect("文件不存在").kind();
+        assert_eq!(kind, FileKind::Custom(0));
+        assert_eq!(dirs.header().category_name(kind.category()), "rust");
+        assert_eq!(dirs.header().category_name(Category::Video), "视频");
+    }
+
+    #[test]
+    fn test_sniff() {
+        let tmp = tempfile::tempdir().expect("创建临时目录失败");
+        let elf = [&b"\x7fELF\x02\x01\x01"[..], &[0; 64]].concat();
+        for (name, content) in [
+            ("photo.dat", &b"\x89PNG\r\n\x1a\n\0\0\0\rIHDR"[..]),
+            ("fake.png", b"\xff\xd8\xff\xe0\0\x10JFIF\0"),
+            ("real.jpg", b"\xff\xd8\xff\xe0\0\x10JFIF\0"),
+            ("notes.txt", b"hello"),
+            ("tool", &elf),
+        ] {
+            fs::write(tmp.path().join(name), content).expect("写入文件失败");
+        }
+
+        let start = tmp.path().to_str().expect("路径无效");
+        let dirs = crate::scan(start, crate::ScanOptions::default()).expect("扫描失败");
+        assert!(
+            dirs.file("fake.png")
+                .expect("文件不存在")
+                .content_kind()
+                .is_none()
+        );
+
+        let options = crate::ScanOptions {
+            sniff: true,
+            ..Default::default()
+        };
+        let dirs = crate::scan(start, options).expect("扫描失败");
+        let dirs = crate::unmap(&dirs.encode().expect("编码失败")).expect("解映射失败");
+        let file = |name: &str| dirs.file(name).expect("文件不存在");
+        assert_eq!(file("photo.dat").kind(), FileKind::Other);
+        assert_eq!(file("photo.dat").content_kind(), Some(FileKind::Png));
+        assert!(file("photo.dat").is_mismatched());
+        assert_eq!(file("fake.png").content_kind(), Some(FileKind::Jpeg));
+        assert!(file("fake.png").is_mismatched());
+        assert!(!file("real.jpg").is_mismatched());
+        // 无法识别内容时不视为不一致
+        assert_eq!(file("notes.txt").content_kind(), Some(FileKind::Other));
+        assert!(!file("notes.txt").is_mismatched());
+        assert_eq!(file("tool").content_kind(), Some(FileKind::Binary));
+    }
+}
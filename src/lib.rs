@@ -1,210 +1,969 @@
-use std::{collections::HashMap, path::Path};
+use std::{
+    borrow::Cow,
+    collections::HashMap,
+    ops::{AddAssign, SubAssign},
+    sync::Arc,
+};
 
-use anyhow::{Context, Result, anyhow};
-use bincode::{Decode, Encode, config};
-use parking_lot::Mutex;
-use path_slash::PathExt;
-use rayon::prelude::*;
+use anyhow::{Result, anyhow, bail};
+use bincode::{Decode, Encode};
 use serde::{Deserialize, Serialize};
-use walkdir::{DirEntry, WalkDir};
 
-#[derive(Debug, Clone, Decode, Encode, Default, Serialize, Deserialize)]
+use crate::stats::Tally;
+pub use crate::{
+    digest::Digest,
+    dupes::{DupeOptions, DuplicateSet, Duplicates},
+    error::{ErrorKind, ScanError},
+    header::{
+        FORMAT_VERSION, HashMode, Header, IgnoreFile, MAGIC, PerceptualHash, ScanOptions,
+        SymlinkPolicy, format_version,
+    },
+    image::ImageInfo,
+    kind::{CUSTOM_BASE, Category, CustomCategory, FileKind},
+    migrate::migrate,
+    scan::scan,
+    stats::{KindStats, KindTotals},
+};
+
+mod codec;
+mod digest;
+mod dupes;
+mod edit;
+mod error;
+mod filter;
+mod header;
+mod image;
+mod kind;
+mod migrate;
+mod name;
+mod phash;
+mod scan;
+mod stats;
+
+/// 目录在 [`DirMap`] 中的编号, 扫描根目录固定为 [`DirId::ROOT`]
+#[derive(
+    Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord, Encode, Decode, Serialize, Deserialize,
+)]
+pub struct DirId(u32);
+
+impl DirId {
+    pub const ROOT: DirId = DirId(0);
+
+    pub fn index(self) -> usize {
+        self.0 as usize
+    }
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
 pub struct Dir {
-    size: u64,
+    /// 目录名, 根目录为空, 扫描根目录只记录在映射头中
+    name: Arc<str>,
+    /// 名称不是合法 UTF-8, `name` 为转义形式
+    raw: bool,
+    parent: Option<DirId>,
+    own_size: u64,
+    own_disk_size: u64,
+    total: Totals,
     file: Vec<File>,
-    children: Vec<String>,
+    children: Vec<DirId>,
+    links: Vec<Link>,
+    boundary: Option<Boundary>,
+    /// 未展开的内容的统计, 已计入 `total`
+    unexpanded: Totals,
+    /// 按类型与扩展名分类的递归统计, 与 `total` 一同维护
+    stats: KindStats,
+    /// 未展开的内容的分类统计, 已计入 `stats`; 旧格式的映射没有记录
+    unexpanded_stats: Option<Box<KindStats>>,
+    /// 目录自身的元数据, 扫描时启用 [`ScanOptions::metadata`] 才会记录
+    metadata: Option<Box<Metadata>>,
+}
+
+/// 扫描在目录处停止、未展开其内容的原因
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Encode, Decode, Serialize, Deserialize)]
+pub enum Boundary {
+    /// 其他文件系统的挂载点
+    MountPoint,
+    /// 达到最大扫描深度
+    MaxDepth,
+}
+
+/// 目录的递归统计, 包含所有子孙目录
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Decode, Encode, Serialize, Deserialize)]
+pub struct Totals {
+    /// 表观大小, 即文件长度之和
+    pub size: u64,
+    /// 实际占用的磁盘空间
+    pub disk_size: u64,
+    pub files: u64,
+    pub dirs: u64,
+    pub links: u64,
+}
+
+/// 文件或目录的时间戳、权限与属主, 时间为 Unix 时间戳 (秒)
+///
+/// 非 Unix 平台上 `ctime` 为创建时间, `mode` 只反映只读属性 (`0o444` 或 `0o666`), `uid` 与 `gid` 为 0
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Encode, Decode, Serialize, Deserialize)]
+pub struct Metadata {
+    /// 内容修改时间
+    pub mtime: i64,
+    /// Unix 上为状态改变时间
+    pub ctime: i64,
+    /// 访问时间
+    pub atime: i64,
+    /// 权限位, 包含文件类型位
+    pub mode: u32,
+    pub uid: u32,
+    pub gid: u32,
 }
 
-#[derive(Debug, Clone, Encode, Decode, Serialize, Deserialize)]
+/// 符号链接条目, 不计入大小
+#[derive(Debug, Clone, PartialEq, Eq, Encode, Decode, Serialize, Deserialize)]
+pub struct Link {
+    name: String,
+    target: String,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct File {
     typ: u8,
-    name: String,
+    /// 名称不是合法 UTF-8, `name` 为转义形式
+    raw: bool,
+    name: Arc<str>,
     size: u64,
+    disk_size: u64,
+    hardlink: Option<HardLink>,
+    /// 扫描时启用 [`ScanOptions::metadata`] 才会记录
+    metadata: Option<Box<Metadata>>,
+    /// 按 [`ScanOptions::hash`] 计算的内容摘要
+    digest: Option<Box<Digest>>,
+    /// 启用 [`ScanOptions::sniff`] 时按文件内容识别的类型编码
+    content: Option<u8>,
+    /// 启用 [`ScanOptions::images`] 时记录的图片信息
+    image: Option<Box<ImageInfo>>,
+    /// 启用 [`ScanOptions::perceptual`] 时计算的图片感知哈希
+    phash: Option<u64>,
+}
+
+/// 硬链接信息, 仅在文件的链接数大于 1 时记录
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Encode, Decode, Serialize, Deserialize)]
+pub struct HardLink {
+    dev: u64,
+    ino: u64,
+    primary: bool,
+}
+
+impl AddAssign for Totals {
+    fn add_assign(&mut self, rhs: Self) {
+        self.size += rhs.size;
+        self.disk_size += rhs.disk_size;
+        self.files += rhs.files;
+        self.dirs += rhs.dirs;
+        self.links += rhs.links;
+    }
+}
+
+impl SubAssign for Totals {
+    fn sub_assign(&mut self, rhs: Self) {
+        self.size -= rhs.size;
+        self.disk_size -= rhs.disk_size;
+        self.files -= rhs.files;
+        self.dirs -= rhs.dirs;
+        self.links -= rhs.links;
+    }
 }
 
 impl Dir {
+    /// 直接文件的大小之和
+    pub fn own_size(&self) -> u64 {
+        self.own_size
+    }
+
+    /// 直接文件占用的磁盘空间之和
+    pub fn own_disk_size(&self) -> u64 {
+        self.own_disk_size
+    }
+
+    /// 包含所有子孙目录的总大小
+    pub fn total_size(&self) -> u64 {
+        self.total.size
+    }
+
+    /// 包含所有子孙目录占用的磁盘空间
+    pub fn total_disk_size(&self) -> u64 {
+        self.total.disk_size
+    }
+
+    pub fn totals(&self) -> Totals {
+        self.total
+    }
+
+    /// 按文件类型与扩展名分类的递归数量与大小
+    pub fn kind_stats(&self) -> &KindStats {
+        &self.stats
+    }
+
+    /// 未展开目录中汇总内容的分类统计
+    pub fn unexpanded_kind_stats(&self) -> Option<&KindStats> {
+        self.unexpanded_stats.as_deref()
+    }
+
+    /// 汇总了未展开的内容, 却没有对应的分类统计
+    fn lacks_unexpanded_stats(&self) -> bool {
+        self.unexpanded.files > 0 && self.unexpanded_stats.is_none()
+    }
+
+    /// 作为子目录计入父目录时贡献的统计, 包含自身
+    fn subtree_totals(&self) -> Totals {
+        Totals {
+            dirs: self.total.dirs + 1,
+            ..self.total
+        }
+    }
+
+    /// 目录下的直接文件
+    pub fn files(&self) -> impl ExactSizeIterator<Item = &File> {
+        self.file.iter()
+    }
+
+    /// 按转义形式的文件名查找直接文件
+    pub fn file(&self, name: &str) -> Option<&File> {
+        let name = name::normalize(name);
+        self.file.iter().find(|f| *f.name == *name)
+    }
+
+    /// 目录名的转义形式, 非法字节写作 `\xNN`, 反斜杠写作 `\\`; 根目录为空
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    /// 名称是否不是合法 UTF-8
+    pub fn is_raw_name(&self) -> bool {
+        self.raw
+    }
+
+    /// 名称的原始字节
+    pub fn raw_name(&self) -> Cow<'_, [u8]> {
+        name::to_bytes(&self.name)
+    }
+
+    /// 目录自身的时间戳、权限与属主, 扫描时未启用 [`ScanOptions::metadata`] 时为 `None`
+    pub fn metadata(&self) -> Option<&Metadata> {
+        self.metadata.as_deref()
+    }
+
+    /// 父目录, 根目录为 `None`
+    pub fn parent(&self) -> Option<DirId> {
+        self.parent
+    }
+
+    /// 直接子目录的编号, 可用于 [`DirMap::dir`]
+    pub fn children(&self) -> impl ExactSizeIterator<Item = DirId> {
+        self.children.iter().copied()
+    }
+
+    /// 直接文件数
+    pub fn file_count(&self) -> usize {
+        self.file.len()
+    }
+
+    /// 递归文件数
+    pub fn total_file_count(&self) -> u64 {
+        self.total.files
+    }
+
+    /// 目录下的直接符号链接
+    pub fn links(&self) -> impl ExactSizeIterator<Item = &Link> {
+        self.links.iter()
+    }
+
+    /// 递归符号链接数
+    pub fn total_link_count(&self) -> u64 {
+        self.total.links
+    }
+
+    /// 扫描未展开此目录时的原因
+    pub fn boundary(&self) -> Option<Boundary> {
+        self.boundary
+    }
+
+    /// 未展开的内容的统计, 仅在汇总超过最大深度的条目时不为零
+    pub fn unexpanded_totals(&self) -> Totals {
+        self.unexpanded
+    }
+
+    /// 直接子目录数
+    pub fn child_count(&self) -> usize {
+        self.children.len()
+    }
+
+    /// 递归子目录数, 不含自身
+    pub fn total_dir_count(&self) -> u64 {
+        self.total.dirs
+    }
+
+    /// 以下修改只作用于当前目录, 修改映射中的目录请使用 [`DirMap::insert_file`] 等方法
     pub fn add_file(&mut self, file: File) {
-        self.size += file.size;
+        self.own_size += file.counted_size();
+        self.own_disk_size += file.counted_disk_size();
+        self.total.size += file.counted_size();
+        self.total.disk_size += file.counted_disk_size();
+        self.total.files += 1;
+        self.stats += &KindStats::of_file(&file);
+        self.file.push(file);
+    }
+
+    /// 只累加直接文件的大小, 递归统计由 [`DirMap::with_totals`] 统一计算
+    pub(crate) fn push_file(&mut self, file: File) {
+        self.own_size += file.counted_size();
+        self.own_disk_size += file.counted_disk_size();
         self.file.push(file);
     }
 
     pub fn remove_file(&mut self, files: Vec<String>) {
-        self.file.retain(|f| !files.contains(&f.name));
-        self.size = self.file.iter().map(|f| f.size).sum();
+        let (removed, kept) = self
+            .file
+            .drain(..)
+            .partition(|f| files.iter().any(|name| **name == *f.name));
+        self.file = kept;
+        for file in removed {
+            self.own_size -= file.counted_size();
+            self.own_disk_size -= file.counted_disk_size();
+            self.total.size -= file.counted_size();
+            self.total.disk_size -= file.counted_disk_size();
+            self.total.files -= 1;
+            self.stats -= &KindStats::of_file(&file);
+        }
+    }
+
+    /// 添加子目录, `child` 为子目录本身, 用于累加其统计
+    pub fn add_child(&mut self, id: DirId, child: &Dir) {
+        self.total += child.subtree_totals();
+        self.stats += &child.stats;
+        self.children.push(id);
+    }
+
+    /// 移除子目录, `child` 为子目录本身, 用于扣除其统计
+    pub fn remove_child(&mut self, id: DirId, child: &Dir) {
+        let before = self.children.len();
+        self.children.retain(|&c| c != id);
+        if self.children.len() != before {
+            self.total -= child.subtree_totals();
+            self.stats -= &child.stats;
+        }
+    }
+}
+
+impl Link {
+    /// 链接名的转义形式, 与 [`File::name`] 相同
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    /// 链接指向的路径, 分隔符为 `/`, 其余与链接中保存的原文一致, 按 [`File::name`] 的规则转义
+    pub fn target(&self) -> &str {
+        &self.target
     }
 
-    pub fn add_child(&mut self, child: String) {
-        self.children.push(child);
+    /// 链接名的原始字节
+    pub fn raw_name(&self) -> Cow<'_, [u8]> {
+        name::to_bytes(&self.name)
     }
 
-    pub fn remove_child(&mut self, children: Vec<String>) {
-        self.children.retain(|c| !children.contains(c));
+    /// 链接目标的原始字节, 分隔符为 `/`
+    pub fn raw_target(&self) -> Cow<'_, [u8]> {
+        name::to_bytes(&self.target)
+    }
+}
+
+impl HardLink {
+    /// 设备号与 inode 号, 相同即为同一物理文件
+    pub fn id(&self) -> (u64, u64) {
+        (self.dev, self.ino)
+    }
+
+    /// 是否为计入大小的那个路径
+    pub fn is_primary(&self) -> bool {
+        self.primary
     }
 }
 
 impl File {
-    fn new(entry: &DirEntry) -> Result<Self> {
-        let name = entry.file_name().to_string_lossy().into_owned();
-        let metadata = entry
-            .metadata()
-            .with_context(|| format!("无法获取元数据: {name}"))?;
-
-        Ok(File {
-            typ: Self::recognize_file_type(entry.path()),
-            name,
-            size: metadata.len(),
+    /// 文件名的转义形式, 非法字节写作 `\xNN`, 反斜杠写作 `\\`
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    /// 名称是否不是合法 UTF-8
+    pub fn is_raw_name(&self) -> bool {
+        self.raw
+    }
+
+    /// 名称的原始字节
+    pub fn raw_name(&self) -> Cow<'_, [u8]> {
+        name::to_bytes(&self.name)
+    }
+
+    pub fn size(&self) -> u64 {
+        self.size
+    }
+
+    /// 计入目录大小的部分, 同一物理文件的多个硬链接只有一个计入
+    pub fn counted_size(&self) -> u64 {
+        match self.hardlink {
+            Some(link) if !link.primary => 0,
+            _ => self.size,
+        }
+    }
+
+    /// 实际占用的磁盘空间, Unix 上为已分配块数 × 512, 其他平台等于文件长度
+    pub fn disk_size(&self) -> u64 {
+        self.disk_size
+    }
+
+    /// 计入目录磁盘占用的部分, 规则同 [`File::counted_size`]
+    pub fn counted_disk_size(&self) -> u64 {
+        match self.hardlink {
+            Some(link) if !link.primary => 0,
+            _ => self.disk_size,
+        }
+    }
+
+    pub fn hardlink(&self) -> Option<HardLink> {
+        self.hardlink
+    }
+
+    /// 时间戳、权限与属主, 扫描时未启用 [`ScanOptions::metadata`] 时为 `None`
+    pub fn metadata(&self) -> Option<&Metadata> {
+        self.metadata.as_deref()
+    }
+
+    /// 内容摘要, 未按 [`ScanOptions::hash`] 计算时为 `None`
+    pub fn digest(&self) -> Option<&Digest> {
+        self.digest.as_deref()
+    }
+
+    /// 图片的尺寸、帧数与 EXIF 信息, 未启用 [`ScanOptions::images`] 或无法解析时为 `None`
+    pub fn image(&self) -> Option<&ImageInfo> {
+        self.image.as_deref()
+    }
+
+    /// 图片的感知哈希, 未启用 [`ScanOptions::perceptual`] 或无法解码时为 `None`
+    pub fn perceptual_hash(&self) -> Option<u64> {
+        self.phash
+    }
+
+    /// 按扩展名与自定义类别识别的类型
+    pub fn kind(&self) -> FileKind {
+        FileKind::from_code(self.typ)
+    }
+
+    /// 按文件开头的字节识别的类型, 未启用 [`ScanOptions::sniff`] 时为 `None`,
+    /// 无法识别时为 [`FileKind::Other`]
+    pub fn content_kind(&self) -> Option<FileKind> {
+        self.content.map(FileKind::from_code)
+    }
+
+    /// 内容与扩展名表示的类型不一致, 如扩展名为 `.png` 的 JPEG 文件
+    ///
+    /// 内容无法识别或扩展名属于自定义类别时不视为不一致
+    pub fn is_mismatched(&self) -> bool {
+        let kind = self.kind();
+        self.content_kind().is_some_and(|content| {
+            content != FileKind::Other && content != kind && !matches!(kind, FileKind::Custom(_))
         })
     }
 
-    fn recognize_file_type(file: &Path) -> u8 {
-        file.extension()
-            .and_then(|ext| ext.to_str())
-            .map(|ext| match ext.to_lowercase().as_str() {
-                "png" => 0,
-                "jpg" | "jpeg" => 1,
-                "webp" => 2,
-                "svg" => 3,
-                "gif" => 4,
-                _ => 5,
-            })
-            .unwrap_or(5)
+    /// 原始类型编码
+    pub fn typ(&self) -> u8 {
+        self.typ
+    }
+
+    /// 按文件名识别内置类型, `name` 为转义形式, 不构成转义的反斜杠按字面处理
+    pub fn new(name: impl Into<Arc<str>>, size: u64) -> Self {
+        let name = name.into();
+        let name = match name::normalize(&name) {
+            Cow::Borrowed(_) => name,
+            Cow::Owned(normalized) => normalized.into(),
+        };
+        File {
+            typ: FileKind::from_name(&name).code(),
+            raw: name::is_raw(&name),
+            name,
+            size,
+            disk_size: size,
+            hardlink: None,
+            metadata: None,
+            digest: None,
+            content: None,
+            image: None,
+            phash: None,
+        }
     }
 }
 
-pub fn map(start_path: &str) -> Result<Vec<u8>> {
-    let mut tree = build_tree(start_path)?;
-    let sizes = calc_size(&tree, start_path)?;
-    for (path, size) in sizes {
-        tree.get_mut(&path)
-            .ok_or_else(|| anyhow!("目录不存在: {}", path))?
-            .size = size;
-    }
-    let encoded = bincode::encode_to_vec(&tree, config::standard())?;
-    Ok(zstd::encode_all(&encoded[..], 3)?)
-}
-
-pub fn unmap(data: &[u8]) -> Result<HashMap<String, Dir>> {
-    let decompressed = zstd::decode_all(data)?;
-    let (dirs, _) = bincode::decode_from_slice(&decompressed, config::standard())?;
-    Ok(dirs)
-}
-
-fn build_tree(start_path: &str) -> Result<HashMap<String, Dir>> {
-    let dirs = Mutex::new(HashMap::new());
-    let entries: Vec<_> = WalkDir::new(start_path)
-        .into_iter()
-        .filter_map(Result::ok)
-        .collect();
-
-    // 添加所有目录
-    entries
-        .par_iter()
-        .filter(|e| e.file_type().is_dir())
-        .for_each(|entry| {
-            let path = entry.path().to_slash_lossy().into_owned();
-            dirs.lock().entry(path).or_insert_with(Dir::default);
-        });
+/// 解映射后的目录表, 目录存放在以 [`DirId`] 为下标的数组中, 路径按需由目录名拼接
+///
+/// 目录的键为相对扫描根目录、以 `/` 分隔的路径, 与扫描位置和平台无关. 查找时也接受以扫描根目录
+/// 开头的完整路径, 以及以 `\` 分隔的路径
+#[derive(Debug, Clone)]
+pub struct DirMap {
+    header: Header,
+    /// 下标 0 为扫描根目录
+    dirs: Vec<Dir>,
+    errors: Vec<ScanError>,
+}
 
-    // 处理文件和目录关系
-    let error = Mutex::new(None);
-    entries.par_iter().for_each(|entry| {
-        if error.lock().is_some() {
-            return;
-        }
-
-        let path = entry.path();
-        if entry.file_type().is_file() {
-            if let Some(parent) = path.parent() {
-                let parent_path = parent.to_slash_lossy().into_owned();
-                match File::new(entry) {
-                    Ok(file) => {
-                        let mut dirs_lock = dirs.lock();
-                        if let Some(parent_dir) = dirs_lock.get_mut(&parent_path) {
-                            parent_dir.add_file(file);
-                        } else {
-                            *error.lock() = Some(anyhow!("父目录不存在: {}", parent_path));
-                        }
-                    }
-                    Err(e) => {
-                        *error.lock() = Some(e);
-                    }
-                }
-            }
-        } else if entry.file_type().is_dir()
-            && let Some(parent) = path.parent()
+impl DirMap {
+    /// 只包含空的根目录的目录表
+    pub fn new(header: Header) -> Self {
+        DirMap {
+            header,
+            dirs: vec![Dir::default()],
+            errors: Vec::new(),
+        }
+    }
+
+    pub fn header(&self) -> &Header {
+        &self.header
+    }
+
+    /// 容错扫描时跳过的路径
+    pub fn errors(&self) -> &[ScanError] {
+        &self.errors
+    }
+
+    /// 按类别统计跳过的路径数
+    pub fn error_summary(&self) -> HashMap<ErrorKind, usize> {
+        error::summarize(&self.errors)
+    }
+
+    /// 扫描根目录
+    pub fn root(&self) -> &Dir {
+        &self.dirs[DirId::ROOT.index()]
+    }
+
+    /// 根据各目录的直接文件重新计算递归统计, 以 [`DirId::index`] 为下标
+    pub fn calc_size(&self) -> Result<Vec<Totals>> {
+        calc_size(&self.dirs)
+    }
+
+    /// 由直接文件重新累加各目录的递归统计, `dirs` 的首个元素必须为根目录
+    pub(crate) fn with_totals(
+        header: Header,
+        mut dirs: Vec<Dir>,
+        errors: Vec<ScanError>,
+    ) -> Result<Self> {
+        if dirs.is_empty() {
+            bail!("根目录不存在: {}", header.root());
+        }
+        // 旧格式中根目录名为扫描根目录的路径
+        dirs[0].name = Arc::from("");
+        dirs[0].raw = false;
+        aggregate(&mut dirs)?;
+        Ok(DirMap {
+            header,
+            dirs,
+            errors,
+        })
+    }
+
+    /// 按路径查找目录编号, 路径可以是相对路径或以扫描根目录开头的完整路径, 忽略末尾的 `/`
+    pub fn id(&self, path: &str) -> Option<DirId> {
+        self.resolve(path, |path| {
+            self.interpretations(path)
+                .map(|path| self.walk(path))
+                .find(|&(.., complete)| complete)
+                .map(|(id, ..)| id)
+        })
+    }
+
+    /// 按路径查找目录, 忽略末尾的 `/`
+    pub fn get(&self, path: &str) -> Option<&Dir> {
+        self.id(path).and_then(|id| self.dir(id))
+    }
+
+    pub fn dir(&self, id: DirId) -> Option<&Dir> {
+        self.dirs.get(id.index())
+    }
+
+    /// 相对扫描根目录的路径, 即目录的键, 根目录为空
+    pub fn path(&self, id: DirId) -> String {
+        path_of(&self.dirs, id)
+    }
+
+    /// 以扫描根目录开头的完整路径
+    pub fn full_path(&self, id: DirId) -> String {
+        join(self.header.root(), &self.path(id))
+    }
+
+    /// 由原始名称拼接系统路径, 可以访问名称不是合法 UTF-8 的目录
+    pub fn os_path(&self, id: DirId) -> std::path::PathBuf {
+        use std::path::PathBuf;
+
+        let mut names = Vec::new();
+        let mut current = Some(id);
+        while let Some(id) = current
+            && id != DirId::ROOT
         {
-            if parent.as_os_str().is_empty() {
-                return;
-            }
+            let dir = &self.dirs[id.index()];
+            names.push(dir);
+            current = dir.parent;
+        }
+        let mut path = PathBuf::from(self.header.root());
+        for dir in names.into_iter().rev() {
+            path.push(name::to_os(dir.raw_name()));
+        }
+        path
+    }
+
+    pub fn contains(&self, path: &str) -> bool {
+        self.id(path).is_some()
+    }
+
+    /// 按路径查找文件, 如 `src/lib.rs`
+    pub fn file(&self, path: &str) -> Option<&File> {
+        let (dir, index) = self.find_file(path)?;
+        Some(&self.dirs[dir.index()].file[index])
+    }
+
+    /// 直接子目录及其编号
+    pub fn children(&self, path: &str) -> impl Iterator<Item = (DirId, &Dir)> {
+        self.get(path)
+            .into_iter()
+            .flat_map(Dir::children)
+            .map(|id| (id, &self.dirs[id.index()]))
+    }
+
+    /// 指向同一物理文件的路径分组, 每组按路径排序, 首个路径计入大小
+    pub fn hardlinks(&self) -> Vec<Vec<String>> {
+        let mut groups: Vec<_> = hardlink_groups(&self.dirs)
+            .into_values()
+            .filter(|paths| paths.len() > 1)
+            .map(|paths| {
+                paths
+                    .into_iter()
+                    .map(|(dir, index)| {
+                        join(&self.path(dir), &self.dirs[dir.index()].file[index].name)
+                    })
+                    .collect::<Vec<_>>()
+            })
+            .collect();
+        groups.sort();
+        groups
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = (DirId, &Dir)> {
+        self.dirs
+            .iter()
+            .enumerate()
+            .map(|(index, dir)| (DirId(index as u32), dir))
+    }
+
+    /// 所有目录相对扫描根目录的路径, 逐个拼接
+    pub fn paths(&self) -> impl Iterator<Item = String> {
+        (0..self.dirs.len()).map(|index| self.path(DirId(index as u32)))
+    }
+
+    pub fn len(&self) -> usize {
+        self.dirs.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.dirs.is_empty()
+    }
+
+    /// 编码为映射文件内容
+    pub fn encode(&self) -> Result<Vec<u8>> {
+        let encoded = codec::encode(self)?;
+        let mut raw = Vec::with_capacity(encoded.len() / 4);
+        header::write_preamble(&mut raw);
+        zstd::stream::copy_encode(&encoded[..], &mut raw, 3)?;
+        Ok(raw)
+    }
 
-            let parent_path = parent.to_slash_lossy().into_owned();
-            let dir_path = path.to_slash_lossy().into_owned();
+    /// 先按 `/` 分隔查找, 找不到时再把 `\` 也视为分隔符.
+    /// 转义后的原始名称中含有 `\`, 因此不能直接替换
+    fn resolve<T>(&self, path: &str, find: impl Fn(&str) -> Option<T>) -> Option<T> {
+        find(path).or_else(|| {
+            path.contains('\\')
+                .then(|| find(&path.replace('\\', "/")))
+                .flatten()
+        })
+    }
 
-            let mut dirs_lock = dirs.lock();
-            if let Some(parent_dir) = dirs_lock.get_mut(&parent_path) {
-                parent_dir.add_child(dir_path);
-            } else {
-                *error.lock() = Some(anyhow!("父目录不存在: {}", parent_path));
+    /// 路径的可能解释, 先视为相对路径, 再视为以扫描根目录开头的完整路径
+    ///
+    /// 因此子目录与扫描根目录同名时, 相对路径优先; 与扫描根目录完全相同的路径总是指根目录,
+    /// 同名的子目录可以写作 `./名称`
+    fn interpretations<'a>(&self, path: &'a str) -> impl Iterator<Item = &'a str> {
+        let root = self.header.root();
+        let (relative, absolute) = match path.strip_prefix(root) {
+            Some(rest) if rest.chars().all(|c| c == '/') => (None, Some(rest)),
+            // 根目录为 `/` 或 `C:/` 时, 子路径紧接在根目录之后
+            Some(rest) if root.ends_with('/') || rest.starts_with('/') => (Some(path), Some(rest)),
+            _ => (Some(path), None),
+        };
+        relative.into_iter().chain(absolute)
+    }
+
+    /// 从根目录沿相对路径逐级查找, 返回能找到的最深的目录、找到的层数以及是否全部找到
+    fn walk(&self, path: &str) -> (DirId, usize, bool) {
+        let mut id = DirId::ROOT;
+        for (depth, name) in components(path).enumerate() {
+            match self.child(id, name) {
+                Some(child) => id = child,
+                None => return (id, depth, false),
             }
         }
-    });
+        (id, 0, true)
+    }
+
+    /// 按名称查找子目录, 名称先转为规范的转义形式
+    fn child(&self, id: DirId, name: &str) -> Option<DirId> {
+        let name = name::normalize(name);
+        self.dirs
+            .get(id.index())?
+            .children()
+            .find(|child| *self.dirs[child.index()].name == *name)
+    }
+
+    /// 新建或移入的目录路径, 已存在时取查找到的解释, 否则取已存在部分最深的解释
+    ///
+    /// 与 [`resolve`](Self::resolve) 一致, 含 `\` 的路径另视为以 `\` 分隔; 层数相同时
+    /// 按 `/` 分隔的解释优先, 同一写法中以扫描根目录开头的完整路径优先
+    fn destination(&self, path: &str) -> String {
+        let replaced = path.contains('\\').then(|| path.replace('\\', "/"));
+        let candidates: Vec<(bool, &str)> = self
+            .interpretations(path)
+            .map(|path| (true, path))
+            .chain(
+                replaced
+                    .iter()
+                    .flat_map(|path| self.interpretations(path).map(|path| (false, path))),
+            )
+            .collect();
+        let walks: Vec<_> = candidates
+            .iter()
+            .map(|&(_, path)| self.walk(path))
+            .collect();
+        let best = walks
+            .iter()
+            .position(|&(.., complete)| complete)
+            .or_else(|| (0..candidates.len()).max_by_key(|&i| (walks[i].1, candidates[i].0, i)))
+            .unwrap_or_default();
+        candidates
+            .get(best)
+            .map_or_else(String::new, |&(_, path)| path.to_string())
+    }
 
-    if let Some(err) = error.lock().take() {
-        return Err(err);
+    fn locate_in<'a>(&self, path: &'a str) -> Option<(DirId, &'a str)> {
+        let mut names: Vec<_> = components(path).collect();
+        let name = names.pop()?;
+        let dir = names
+            .into_iter()
+            .try_fold(DirId::ROOT, |id, name| self.child(id, name))?;
+        Some((dir, name))
     }
 
-    Ok(dirs.into_inner())
+    /// 已存在的文件所在目录与文件下标
+    fn find_file(&self, path: &str) -> Option<(DirId, usize)> {
+        self.resolve(path, |path| {
+            self.interpretations(path).find_map(|path| {
+                let (dir, name) = self.locate_in(path)?;
+                let name = name::normalize(name);
+                let index = self.dirs[dir.index()]
+                    .file
+                    .iter()
+                    .position(|f| *f.name == *name)?;
+                Some((dir, index))
+            })
+        })
+    }
 }
 
-pub fn calc_size(dirs: &HashMap<String, Dir>, start_path: &str) -> Result<HashMap<String, u64>> {
-    let mut sizes = HashMap::new();
+/// 相对路径中的各级目录名
+fn components(path: &str) -> impl Iterator<Item = &str> {
+    path.split('/')
+        .filter(|name| !name.is_empty() && *name != ".")
+}
 
-    // 使用枚举表示栈中的操作类型
-    enum StackItem<'a> {
-        Process(&'a str),   // 处理目录
-        Calculate(&'a str), // 计算目录大小
+pub fn map(start_path: &str) -> Result<Vec<u8>> {
+    map_with(start_path, ScanOptions::default())
+}
+
+pub fn map_with(start_path: &str, options: ScanOptions) -> Result<Vec<u8>> {
+    scan(start_path, options)?.encode()
+}
+
+/// 解码映射文件, 旧格式会自动升级到当前格式
+pub fn unmap(data: &[u8]) -> Result<DirMap> {
+    let (version, compressed) = header::read_preamble(data)?;
+    let decompressed = zstd::decode_all(compressed)?;
+    if version < FORMAT_VERSION {
+        return migrate::upgrade(version, &decompressed);
     }
+    codec::decode(&decompressed)
+}
+
+/// 以 `/` 连接路径, 任一部分为空时返回另一部分
+fn join(parent: &str, name: &str) -> String {
+    match (parent, name) {
+        ("", name) => name.to_string(),
+        (parent, "") => parent.to_string(),
+        (parent, name) if parent.ends_with('/') => format!("{parent}{name}"),
+        (parent, name) => format!("{parent}/{name}"),
+    }
+}
 
-    let mut stack = vec![StackItem::Process(start_path)];
+/// 由目录名拼接相对扫描根目录的路径
+fn path_of(dirs: &[Dir], id: DirId) -> String {
+    let mut names = Vec::new();
+    let mut current = Some(id);
+    while let Some(id) = current {
+        let dir = &dirs[id.index()];
+        names.push(&*dir.name);
+        current = dir.parent;
+    }
+    let mut path = String::new();
+    for name in names.into_iter().rev() {
+        if !path.is_empty() && !path.ends_with('/') {
+            path.push('/');
+        }
+        path.push_str(name);
+    }
+    path
+}
 
+/// 按物理文件分组, 组内按路径排序, 元素为 (目录, 文件下标)
+fn hardlink_groups(dirs: &[Dir]) -> HashMap<(u64, u64), Vec<(DirId, usize)>> {
+    let mut groups: HashMap<_, Vec<_>> = HashMap::new();
+    for (id, dir) in dirs.iter().enumerate() {
+        for (index, file) in dir.file.iter().enumerate() {
+            if let Some(link) = file.hardlink {
+                groups
+                    .entry(link.id())
+                    .or_default()
+                    .push((DirId(id as u32), index));
+            }
+        }
+    }
+    let mut paths = HashMap::new();
+    for group in groups.values_mut() {
+        for &(dir, _) in group.iter() {
+            paths.entry(dir).or_insert_with(|| path_of(dirs, dir));
+        }
+        group.sort_by(|a, b| {
+            (&paths[&a.0], &dirs[a.0.index()].file[a.1].name)
+                .cmp(&(&paths[&b.0], &dirs[b.0.index()].file[b.1].name))
+        });
+    }
+    groups
+}
+
+/// 子目录先于父目录的遍历顺序, 从根目录开始
+fn post_order(dirs: &[Dir]) -> Result<Vec<DirId>> {
+    let mut order = Vec::with_capacity(dirs.len());
+
+    // 使用枚举表示栈中的操作类型
+    enum StackItem {
+        Process(DirId),   // 处理目录
+        Calculate(DirId), // 所有子目录处理完毕
+    }
+
+    let mut stack = vec![StackItem::Process(DirId::ROOT)];
     while let Some(item) = stack.pop() {
         match item {
-            StackItem::Process(path) => {
-                let dir = dirs
-                    .get(path)
-                    .ok_or_else(|| anyhow!("目录不存在: {}", path))?;
+            StackItem::Process(id) => {
+                let dir = get_dir(dirs, id)?;
 
-                // 先推入计算操作，确保在处理完所有子目录后计算当前目录的大小
-                stack.push(StackItem::Calculate(path));
+                // 先推入计算操作，确保在处理完所有子目录后再计算当前目录
+                stack.push(StackItem::Calculate(id));
 
                 // 然后推入所有子目录的处理操作
-                for child in &dir.children {
+                for &child in &dir.children {
                     stack.push(StackItem::Process(child));
                 }
             }
-            StackItem::Calculate(path) => {
-                let dir = dirs
-                    .get(path)
-                    .ok_or_else(|| anyhow!("目录不存在: {}", path))?;
+            StackItem::Calculate(id) => order.push(id),
+        }
+    }
 
-                let mut size = dir.size;
+    Ok(order)
+}
 
-                for child in &dir.children {
-                    size += sizes
-                        .get(child)
-                        .ok_or_else(|| anyhow!("目录不存在: {}", path))?;
-                }
+fn get_dir(dirs: &[Dir], id: DirId) -> Result<&Dir> {
+    dirs.get(id.index())
+        .ok_or_else(|| anyhow!("目录不存在: #{}", id.0))
+}
 
-                sizes.insert(path.to_string(), size);
-            }
+/// 目录自身的直接统计, 不含子目录
+fn own_totals(dir: &Dir) -> Totals {
+    let mut total = Totals {
+        size: dir.own_size,
+        disk_size: dir.own_disk_size,
+        files: dir.file.len() as u64,
+        dirs: dir.children.len() as u64,
+        links: dir.links.len() as u64,
+    };
+    total += dir.unexpanded;
+    total
+}
+
+/// 从根目录开始计算各目录的递归统计, 包含表观大小与磁盘占用
+fn calc_size(dirs: &[Dir]) -> Result<Vec<Totals>> {
+    let mut totals = vec![Totals::default(); dirs.len()];
+    for id in post_order(dirs)? {
+        let dir = get_dir(dirs, id)?;
+        let mut total = own_totals(dir);
+        for &child in &dir.children {
+            total += totals[child.index()];
         }
+        totals[id.index()] = total;
+    }
+    Ok(totals)
+}
+
+/// 在同一遍后序遍历中写入各目录的递归统计与分类统计
+///
+/// 每个目录都必须能从根目录到达, 否则映射文件已损坏, 例如子目录编号形成了脱离根目录的环
+fn aggregate(dirs: &mut [Dir]) -> Result<()> {
+    let order = post_order(dirs)?;
+    if order.len() != dirs.len() {
+        bail!(
+            "映射文件损坏: {} 个目录无法从根目录到达",
+            dirs.len() - order.len()
+        );
     }
+    let mut tally = Tally::default();
+    for id in order {
+        let dir = &dirs[id.index()];
+        let mut total = own_totals(dir);
+        for file in &dir.file {
+            tally.add_file(file);
+        }
+        if let Some(stats) = &dir.unexpanded_stats {
+            tally.add(stats);
+        }
+        if dir.lacks_unexpanded_stats() {
+            tally.set_incomplete();
+        }
+        for &child in &dir.children {
+            let child = &dirs[child.index()];
+            total += child.total;
+            tally.add(&child.stats);
+        }
 
-    Ok(sizes)
+        let dir = &mut dirs[id.index()];
+        dir.total = total;
+        dir.stats = tally.finish();
+    }
+    Ok(())
 }
 
 #[cfg(test)]
 mod tests {
+    use bincode::config;
+
     use super::*;
 
     #[test]
@@ -219,4 +978,215 @@ mod tests {
         let dirs = unmap(&data).expect("解映射失败");
         println!("{dirs:?}");
     }
+
+    #[test]
+    fn test_unreachable_dirs() {
+        let mut dirs = DirMap::new(Header::new("root".to_string(), ScanOptions::default()));
+        let a = dirs.create_dir("a").expect("创建目录失败");
+        let b = dirs.create_dir("b").expect("创建目录失败");
+        assert!(unmap(&dirs.encode().expect("编码失败")).is_ok());
+
+        // `a` 与 `b` 互为子目录, 各自只有一个父目录, 但都无法从根目录到达
+        dirs.dirs[DirId::ROOT.index()].children.clear();
+        dirs.dirs[a.index()].children.push(b);
+        dirs.dirs[b.index()].children.push(a);
+        let err = unmap(&dirs.encode().expect("编码失败")).expect_err("应拒绝不可达的目录");
+        assert!(err.to_string().contains("无法从根目录到达"), "{err:#}");
+    }
+
+    #[test]
+    fn test_read_api() {
+        let raw = map("src").expect("映射失败");
+        let dirs = unmap(&raw).expect("解映射失败");
+
+        let root = dirs.get("src/").expect("根目录不存在");
+        assert!(root.files().any(|f| f.name() == "lib.rs"));
+
+        let lib = dirs.file("src/lib.rs").expect("文件不存在");
+        assert_eq!(lib.kind(), FileKind::Source);
+
+        let children: u64 = dirs.children("src").map(|(_, dir)| dir.total_size()).sum();
+        let files: u64 = root.files().map(File::size).sum();
+        assert_eq!(root.own_size(), files);
+        assert_eq!(root.total_size(), children + files);
+        assert_eq!(
+            root.total_file_count(),
+            root.file_count() as u64
+                + dirs
+                    .children("src")
+                    .map(|(_, dir)| dir.total_file_count())
+                    .sum::<u64>()
+        );
+        assert_eq!(root.total_dir_count(), dirs.len() as u64 - 1);
+    }
+
+    #[test]
+    fn test_header() {
+        let raw = map("src").expect("映射失败");
+        assert_eq!(raw[..4], MAGIC);
+
+        let dirs = unmap(&raw).expect("解映射失败");
+        assert_eq!(dirs.header().root(), "src");
+        assert_eq!(dirs.header().version(), env!("CARGO_PKG_VERSION"));
+        assert_eq!(
+            dirs.calc_size().expect("计算大小失败")[DirId::ROOT.index()],
+            dirs.root().totals()
+        );
+
+        let err = unmap(b"not a map").expect_err("应当拒绝非映射文件");
+        assert!(err.to_string().contains("不是 dirmap 映射文件"));
+
+        let mut bumped = raw.clone();
+        bumped[4..6].copy_from_slice(&(FORMAT_VERSION + 1).to_le_bytes());
+        assert!(unmap(&bumped).is_err());
+    }
+
+    #[test]
+    fn test_paths() {
+        let raw = map("src").expect("映射失败");
+        let dirs = unmap(&raw).expect("解映射失败");
+        for (id, dir) in dirs.iter() {
+            let path = dirs.path(id);
+            assert_eq!(dirs.id(&path), Some(id));
+            assert_eq!(dirs.id(&format!("{path}/")), Some(id));
+            assert_eq!(dirs.id(&dirs.full_path(id)), Some(id));
+            assert_eq!(dirs.id(&path.replace('/', "\\")), Some(id));
+            if let Some(parent) = dir.parent() {
+                assert_eq!(path, join(&dirs.path(parent), dir.name()));
+            }
+        }
+        assert_eq!(dirs.path(DirId::ROOT), "");
+        assert_eq!(dirs.full_path(DirId::ROOT), "src");
+        for path in [
+            "migrate/v0.rs",
+            "./migrate/v0.rs",
+            "src/migrate/v0.rs",
+            "src\\migrate\\v0.rs",
+        ] {
+            assert!(dirs.file(path).is_some(), "{path}");
+        }
+        assert_eq!(dirs.id("srcx"), None);
+        // 重新编码得到相同的内容
+        assert_eq!(dirs.encode().expect("编码失败")[6..], raw[6..]);
+    }
+
+    #[test]
+    fn test_backslash_names() {
+        let mut dirs = DirMap::new(Header::new("root".to_string(), ScanOptions::default()));
+        // 转义形式的 `x\y`, 以及按字面处理的反斜杠与表示合法字符的 `\x41`
+        dirs.create_dir("x\\\\y").expect("创建目录失败");
+        dirs.insert_file("x\\\\y", File::new("p\\q.png", 1))
+            .expect("插入文件失败");
+        dirs.create_dir("a\\x41").expect("创建目录失败");
+        dirs.rename_dir("a\\x41", "b\\c").expect("移动目录失败");
+        // 父目录存在时 `\` 视为分隔符
+        dirs.create_dir("c").expect("创建目录失败");
+        dirs.create_dir("c\\d").expect("创建目录失败");
+        dirs.create_dir("e").expect("创建目录失败");
+        dirs.rename_dir("e", "c\\moved").expect("移动目录失败");
+
+        let decoded = unmap(&dirs.encode().expect("编码失败")).expect("解映射失败");
+        for dirs in [&dirs, &decoded] {
+            let dir = dirs.get("x\\\\y").expect("目录不存在");
+            assert_eq!(dir.name(), "x\\\\y");
+            assert_eq!(&*dir.raw_name(), b"x\\y");
+            let file = dirs.file("x\\\\y/p\\\\q.png").expect("文件不存在");
+            assert_eq!(&*file.raw_name(), b"p\\q.png");
+            assert!(dirs.get("b\\\\c").is_some());
+            assert!(dirs.id("c/d").is_some() && dirs.id("c/moved").is_some());
+        }
+        assert_eq!(
+            dirs.iter().map(|(id, _)| dirs.path(id)).collect::<Vec<_>>(),
+            decoded
+                .iter()
+                .map(|(id, _)| decoded.path(id))
+                .collect::<Vec<_>>()
+        );
+    }
+
+    #[test]
+    fn test_child_named_like_root() {
+        let mut dirs = DirMap::new(Header::new("data".to_string(), ScanOptions::default()));
+        let inner = dirs.create_dir("./data/inner").expect("创建目录失败");
+        dirs.insert_file("./data/inner", File::new("f.txt", 1))
+            .expect("插入文件失败");
+
+        assert_eq!(dirs.path(inner), "data/inner");
+        assert_eq!(dirs.id("data/inner"), Some(inner));
+        assert_eq!(dirs.id("data/data/inner"), Some(inner));
+        assert!(dirs.file("data/inner/f.txt").is_some());
+        assert!(dirs.file("data/data/inner/f.txt").is_some());
+        // 与扫描根目录完全相同的路径指根目录
+        assert_eq!(dirs.id("data/"), Some(DirId::ROOT));
+        assert_eq!(dirs.id("./data"), dirs.dir(inner).and_then(Dir::parent));
+    }
+
+    #[test]
+    fn test_dir_mutation() {
+        let mut child = Dir::default();
+        child.add_file(File::new("a.png", 3));
+
+        let mut dir = Dir::default();
+        dir.add_file(File::new("b.txt", 2));
+        dir.add_child(DirId(1), &child);
+        assert_eq!((dir.own_size(), dir.total_size()), (2, 5));
+        assert_eq!((dir.total_file_count(), dir.total_dir_count()), (2, 1));
+
+        dir.remove_file(vec!["b.txt".to_string()]);
+        assert_eq!((dir.own_size(), dir.total_size()), (0, 3));
+
+        dir.remove_child(DirId(1), &child);
+        assert_eq!(dir.totals(), Totals::default());
+    }
+
+    #[test]
+    fn test_migrate_v0() {
+        // 版本 0 的 Dir / File 与同字段顺序的元组编码一致
+        type LegacyDir = (u64, Vec<(u8, String, u64)>, Vec<String>);
+        let legacy: HashMap<String, LegacyDir> = HashMap::from([
+            (
+                "src".to_string(),
+                (
+                    5,
+                    vec![(5, "lib.rs".to_string(), 2)],
+                    vec!["src/a".to_string()],
+                ),
+            ),
+            (
+                "src/a".to_string(),
+                (3, vec![(0, "b.png".to_string(), 3)], vec![]),
+            ),
+        ]);
+        let legacy = bincode::encode_to_vec(&legacy, config::standard()).expect("编码失败");
+        let legacy = zstd::encode_all(&legacy[..], 3).expect("压缩失败");
+        assert_eq!(format_version(&legacy).expect("读取版本失败"), 0);
+
+        let upgraded = unmap(&legacy).expect("升级失败");
+        assert_eq!(upgraded.header().root(), "src");
+        assert_eq!(upgraded.len(), 2);
+        let root = upgraded.root();
+        assert_eq!((root.own_size(), root.total_size()), (2, 5));
+        assert_eq!((root.total_file_count(), root.total_dir_count()), (2, 1));
+        assert_eq!(
+            upgraded.file("src/a/b.png").expect("文件不存在").kind(),
+            FileKind::Png
+        );
+        // 旧版本记为 "其他" 的文件按新的内置表重新识别
+        assert_eq!(
+            upgraded.file("src/lib.rs").expect("文件不存在").kind(),
+            FileKind::Source
+        );
+
+        let migrated = migrate(&legacy).expect("迁移失败");
+        assert_eq!(
+            format_version(&migrated).expect("读取版本失败"),
+            FORMAT_VERSION
+        );
+        assert!(
+            unmap(&migrated)
+                .expect("解映射失败")
+                .file("src/lib.rs")
+                .is_some()
+        );
+    }
 }
@@ -1,16 +1,532 @@
-use dirmap::map;
+use std::path::{Path, PathBuf};
+
+use anyhow::{Context, Result, bail};
+use clap::{Args, Parser, Subcommand, ValueEnum};
+use dirmap::{
+    Boundary, CustomCategory, DirMap, DupeOptions, FORMAT_VERSION, FileKind, HashMode, KindTotals,
+    PerceptualHash, ScanOptions, SymlinkPolicy, format_version, migrate, scan, unmap,
+};
+
+#[derive(Parser)]
+#[command(
+    version,
+    about = "扫描目录生成映射文件",
+    args_conflicts_with_subcommands = true
+)]
+struct Cli {
+    #[command(subcommand)]
+    command: Option<Command>,
+
+    #[command(flatten)]
+    scan: ScanArgs,
+}
+
+#[derive(Args)]
+struct ScanArgs {
+    /// 起始路径
+    path: Option<String>,
+
+    /// 跳过无法读取的路径并记录在映射中, 而不是中止扫描
+    #[arg(short = 'k', long)]
+    tolerant: bool,
+
+    /// 符号链接的处理方式: skip 忽略, record 记录为链接, follow 跟随
+    #[arg(long, default_value_t = SymlinkPolicy::Skip)]
+    symlinks: SymlinkPolicy,
+
+    /// 不跨越文件系统边界, 挂载点记录为未展开的目录
+    #[arg(short = 'x', long)]
+    one_file_system: bool,
+
+    /// 只保留匹配的文件, 可重复指定; 不含 `/` 的模式匹配文件名, 否则匹配相对路径
+    #[arg(long, value_name = "GLOB")]
+    include: Vec<String>,
+
+    /// 跳过匹配的文件与目录, 可重复指定, 如 `--exclude target --exclude .git`
+    #[arg(short, long, value_name = "GLOB")]
+    exclude: Vec<String>,
+
+    /// 遵循扫描目录中的 .gitignore、.ignore 与 .dirmapignore
+    #[arg(long)]
+    ignore_files: bool,
+
+    /// 最大扫描深度, 根目录为 0, 达到该深度的目录记录为未展开
+    #[arg(short = 'd', long, value_name = "N")]
+    max_depth: Option<usize>,
+
+    /// 仍遍历超过最大深度的内容, 将其统计汇总到未展开的目录中
+    #[arg(long, requires = "max_depth")]
+    aggregate: bool,
+
+    /// 记录文件与目录的修改时间、权限与属主
+    #[arg(short, long)]
+    metadata: bool,
+
+    /// 计算文件内容摘要的范围: none 不计算, duplicates 只计算大小与其他文件相同的, all 全部计算
+    #[arg(long, value_name = "MODE", default_value_t = HashMode::None)]
+    hash: HashMode,
+
+    /// 读取文件开头的字节识别实际类型, 标记与扩展名不符的文件
+    #[arg(long)]
+    sniff: bool,
+
+    /// 解析图片的尺寸、帧数与 EXIF 拍摄时间和方向
+    #[arg(long)]
+    images: bool,
+
+    /// 为图片计算感知哈希的算法: ahash / dhash / phash, 用于查找相似图片
+    #[arg(long, value_name = "ALGORITHM")]
+    perceptual: Option<PerceptualHash>,
+
+    /// 自定义文件类别, 形如 `design=psd,sketch`, 可重复指定, 优先于内置类型
+    #[arg(long = "category", value_name = "NAME=EXTS")]
+    categories: Vec<CustomCategory>,
+}
+
+#[derive(Subcommand)]
+enum Command {
+    /// 将旧版本映射文件原地升级为当前格式
+    Migrate {
+        /// 映射文件
+        #[arg(required = true)]
+        files: Vec<PathBuf>,
+    },
+    /// 显示目录下各子目录与文件的大小
+    Show {
+        /// 映射文件
+        #[arg(default_value = "map")]
+        map: PathBuf,
+
+        /// 要显示的目录, 默认为扫描根目录
+        #[arg(short, long)]
+        path: Option<String>,
+
+        /// 显示与排序所用的大小
+        #[arg(long, value_enum, default_value_t = SizeKind::Apparent)]
+        size: SizeKind,
+
+        /// 排序方式
+        #[arg(long, value_enum, default_value_t = SortBy::Size)]
+        sort: SortBy,
+
+        /// 最多显示的条目数
+        #[arg(short = 'n', long)]
+        limit: Option<usize>,
+    },
+    /// 按文件类型或扩展名统计目录下的文件数与大小, 包含所有子孙目录
+    Stats {
+        /// 映射文件
+        #[arg(default_value = "map")]
+        map: PathBuf,
+
+        /// 要统计的目录, 默认为扫描根目录
+        #[arg(short, long)]
+        path: Option<String>,
+
+        /// 按扩展名而不是文件类型分组
+        #[arg(short, long)]
+        extensions: bool,
+
+        /// 显示与排序所用的大小
+        #[arg(long, value_enum, default_value_t = SizeKind::Apparent)]
+        size: SizeKind,
+
+        /// 最多显示的条目数
+        #[arg(short = 'n', long)]
+        limit: Option<usize>,
+    },
+    /// 列出指向同一物理文件的硬链接路径
+    Hardlinks {
+        /// 映射文件
+        #[arg(default_value = "map")]
+        map: PathBuf,
+    },
+    /// 查找内容重复的文件, 需要能访问扫描根目录
+    Dupes {
+        /// 映射文件
+        #[arg(default_value = "map")]
+        map: PathBuf,
+
+        /// 直接使用映射中已有的摘要, 不再读取这些文件
+        #[arg(long)]
+        reuse_digests: bool,
+
+        /// 忽略小于该字节数的文件
+        #[arg(long, value_name = "BYTES", default_value_t = 1)]
+        min_size: u64,
+
+        /// 最多显示的重复组数与目录数
+        #[arg(short = 'n', long)]
+        limit: Option<usize>,
+    },
+    /// 按感知哈希查找相似图片, 需要扫描时指定 --perceptual
+    Similar {
+        /// 映射文件
+        #[arg(default_value = "map")]
+        map: PathBuf,
+
+        /// 视为相似的最大汉明距离, 共 64 位
+        #[arg(short, long, default_value_t = 8)]
+        distance: u32,
+    },
+}
+
+#[derive(Clone, Copy, ValueEnum)]
+enum SizeKind {
+    /// 表观大小, 即文件长度
+    Apparent,
+    /// 实际占用的磁盘空间
+    Disk,
+}
+
+#[derive(Clone, Copy, ValueEnum)]
+enum SortBy {
+    /// 按大小从大到小
+    Size,
+    /// 按名称
+    Name,
+}
 
 fn main() {
-    let args = std::env::args().collect::<Vec<_>>();
-    let start_path = if args.len() > 1 {
-        &args[1]
-    } else {
+    if let Err(e) = run(Cli::parse()) {
+        eprintln!("错误：{e:#}");
+        std::process::exit(1);
+    }
+}
+
+fn run(cli: Cli) -> Result<()> {
+    match cli.command {
+        Some(Command::Migrate { files }) => {
+            for file in &files {
+                migrate_file(file)?;
+            }
+            Ok(())
+        }
+        Some(Command::Show {
+            map,
+            path,
+            size,
+            sort,
+            limit,
+        }) => show(&map, path.as_deref(), size, sort, limit),
+        Some(Command::Stats {
+            map,
+            path,
+            extensions,
+            size,
+            limit,
+        }) => stats(&map, path.as_deref(), extensions, size, limit),
+        Some(Command::Hardlinks { map }) => hardlinks(&map),
+        Some(Command::Dupes {
+            map,
+            reuse_digests,
+            min_size,
+            limit,
+        }) => dupes(&map, reuse_digests, min_size, limit),
+        Some(Command::Similar { map, distance }) => similar(&map, distance),
+        None => scan_dir(cli.scan),
+    }
+}
+
+fn scan_dir(args: ScanArgs) -> Result<()> {
+    let Some(start_path) = args.path else {
         eprintln!("请提供起始路径");
         std::process::exit(1);
     };
+    let options = ScanOptions {
+        tolerant: args.tolerant,
+        symlinks: args.symlinks,
+        one_file_system: args.one_file_system,
+        include: args.include,
+        exclude: args.exclude,
+        ignore_files: args.ignore_files,
+        max_depth: args.max_depth,
+        aggregate: args.aggregate,
+        metadata: args.metadata,
+        hash: args.hash,
+        sniff: args.sniff,
+        images: args.images,
+        perceptual: args.perceptual,
+        categories: args.categories,
+    };
+    let dirs = scan(&start_path, options).context("映射失败")?;
+    std::fs::write("map", dirs.encode()?).context("写入文件失败")?;
+
+    if !dirs.errors().is_empty() {
+        let summary = dirs.error_summary();
+        let mut kinds: Vec<_> = summary.iter().collect();
+        kinds.sort_by_key(|(kind, _)| kind.to_string());
+        let kinds: Vec<_> = kinds.iter().map(|(k, n)| format!("{k} {n}")).collect();
+        eprintln!(
+            "{} 个路径无法读取: {}",
+            dirs.errors().len(),
+            kinds.join(", ")
+        );
+        for err in dirs.errors() {
+            eprintln!("  {err}");
+        }
+    }
+    Ok(())
+}
+
+fn read_map(file: &Path) -> Result<DirMap> {
+    let data = std::fs::read(file).with_context(|| format!("读取文件失败: {}", file.display()))?;
+    unmap(&data).with_context(|| format!("解映射失败: {}", file.display()))
+}
+
+fn show(
+    map: &Path,
+    path: Option<&str>,
+    size: SizeKind,
+    sort: SortBy,
+    limit: Option<usize>,
+) -> Result<()> {
+    let dirs = read_map(map)?;
+    let path = path.unwrap_or(dirs.header().root());
+    let dir = dirs
+        .get(path)
+        .with_context(|| format!("目录不存在: {path}"))?;
+
+    let (total, own) = match size {
+        SizeKind::Apparent => (dir.total_size(), dir.own_size()),
+        SizeKind::Disk => (dir.total_disk_size(), dir.own_disk_size()),
+    };
+    println!(
+        "{path}  {} (直接文件 {}), {} 个文件, {} 个目录",
+        format_size(total),
+        format_size(own),
+        dir.total_file_count(),
+        dir.total_dir_count()
+    );
+
+    let mut entries: Vec<(String, u64)> = dirs
+        .children(path)
+        .map(|(_, dir)| {
+            let name = dir.name();
+            let total = match size {
+                SizeKind::Apparent => dir.total_size(),
+                SizeKind::Disk => dir.total_disk_size(),
+            };
+            let name = match dir.boundary() {
+                Some(Boundary::MountPoint) => format!("{name}/ [挂载点]"),
+                Some(Boundary::MaxDepth) => format!("{name}/ [未展开]"),
+                None => format!("{name}/"),
+            };
+            (name, total)
+        })
+        .chain(dir.files().map(|file| {
+            let size = match size {
+                SizeKind::Apparent => file.counted_size(),
+                SizeKind::Disk => file.counted_disk_size(),
+            };
+            let name = match file.content_kind() {
+                Some(kind) if file.is_mismatched() => format!("{} [内容为 {kind}]", file.name()),
+                _ => file.name().to_string(),
+            };
+            (name, size)
+        }))
+        .collect();
+    match sort {
+        SortBy::Size => entries.sort_by(|a, b| b.1.cmp(&a.1).then_with(|| a.0.cmp(&b.0))),
+        SortBy::Name => entries.sort(),
+    }
+
+    for (name, size) in entries.iter().take(limit.unwrap_or(usize::MAX)) {
+        println!("{:>12}  {name}", format_size(*size));
+    }
+    Ok(())
+}
+
+fn format_size(bytes: u64) -> String {
+    const UNITS: [&str; 6] = ["B", "KiB", "MiB", "GiB", "TiB", "PiB"];
+    let mut size = bytes as f64;
+    let mut unit = 0;
+    while size >= 1024.0 && unit < UNITS.len() - 1 {
+        size /= 1024.0;
+        unit += 1;
+    }
+    if unit == 0 {
+        format!("{bytes} B")
+    } else {
+        format!("{size:.1} {}", UNITS[unit])
+    }
+}
+
+fn stats(
+    map: &Path,
+    path: Option<&str>,
+    extensions: bool,
+    size: SizeKind,
+    limit: Option<usize>,
+) -> Result<()> {
+    let dirs = read_map(map)?;
+    let path = path.unwrap_or(dirs.header().root());
+    let dir = dirs
+        .get(path)
+        .with_context(|| format!("目录不存在: {path}"))?;
+    let stats = dir.kind_stats();
+    let mut entries: Vec<(String, KindTotals)> = if extensions {
+        stats
+            .extensions()
+            .map(|(ext, totals)| match ext {
+                "" => ("(无扩展名)".to_string(), totals),
+                ext => (format!(".{ext}"), totals),
+            })
+            .collect()
+    } else {
+        stats
+            .kinds()
+            .map(|(kind, totals)| {
+                let name = match kind {
+                    FileKind::Custom(_) => dirs.header().category_name(kind.category()).into(),
+                    kind => kind.to_string(),
+                };
+                (name, totals)
+            })
+            .collect()
+    };
+    // 旧格式映射中汇总到未展开目录的内容没有分类, 单独列出
+    if !stats.is_complete() {
+        let mut rest = KindTotals {
+            files: dir.total_file_count(),
+            size: dir.total_size(),
+            disk_size: dir.total_disk_size(),
+        };
+        for (_, totals) in stats.kinds() {
+            rest -= totals;
+        }
+        entries.push(("(未分类)".to_string(), rest));
+    }
+    let bytes = |totals: &KindTotals| match size {
+        SizeKind::Apparent => totals.size,
+        SizeKind::Disk => totals.disk_size,
+    };
+    entries.sort_by(|a, b| bytes(&b.1).cmp(&bytes(&a.1)).then_with(|| a.0.cmp(&b.0)));
+
+    println!("{path}  {} 个文件", dir.total_file_count());
+    for (name, totals) in entries.iter().take(limit.unwrap_or(usize::MAX)) {
+        println!(
+            "{:>12}  {:>8} 个  {name}",
+            format_size(bytes(totals)),
+            totals.files
+        );
+    }
+    Ok(())
+}
+
+fn hardlinks(map: &Path) -> Result<()> {
+    let dirs = read_map(map)?;
+    for group in dirs.hardlinks() {
+        // 首个路径计入大小
+        let size = dirs.file(&group[0]).map_or(0, |f| f.size());
+        println!("{size} 字节, {} 个路径:", group.len());
+        for path in group {
+            println!("  {path}");
+        }
+    }
+    Ok(())
+}
+
+fn dupes(map: &Path, reuse_digests: bool, min_size: u64, limit: Option<usize>) -> Result<()> {
+    let dirs = read_map(map)?;
+    let dupes = dirs.duplicates(DupeOptions {
+        reuse_digests,
+        min_size,
+    });
+    for err in &dupes.errors {
+        eprintln!("无法读取: {err}");
+    }
+
+    let limit = limit.unwrap_or(usize::MAX);
+    for set in dupes.sets.iter().take(limit) {
+        println!(
+            "{} x {} 份, 浪费 {}:",
+            format_size(set.size),
+            set.files.len(),
+            format_size(set.wasted())
+        );
+        for path in &set.files {
+            println!("  {path}");
+        }
+    }
+    if !dupes.dirs.is_empty() {
+        println!("\n按目录:");
+    }
+    for (id, size) in dupes.dirs.iter().take(limit) {
+        // 根目录的相对路径为空, 显示为 `.`
+        let path = match dirs.path(*id) {
+            path if path.is_empty() => ".".to_string(),
+            path => path,
+        };
+        println!("{:>12}  {path}/", format_size(*size));
+    }
+    println!(
+        "\n{} 组重复文件, 共浪费 {}",
+        dupes.sets.len(),
+        format_size(dupes.wasted())
+    );
+    Ok(())
+}
+
+fn similar(map: &Path, distance: u32) -> Result<()> {
+    let dirs = read_map(map)?;
+    let Some(algorithm) = dirs.header().options().perceptual else {
+        bail!("映射中没有感知哈希, 请在扫描时指定 --perceptual");
+    };
+    let groups = dirs.similar_images(distance);
+    for group in &groups {
+        println!("{} 张相似图片:", group.len());
+        for path in group {
+            let file = dirs.file(path);
+            let size = format_size(file.map_or(0, |file| file.size()));
+            match file.and_then(|file| file.image()) {
+                Some(image) => println!("  {path}  {}x{}, {size}", image.width, image.height),
+                None => println!("  {path}  {size}"),
+            }
+        }
+    }
+    println!(
+        "\n{} 组相似图片 ({algorithm}, 距离不超过 {distance})",
+        groups.len()
+    );
+    Ok(())
+}
+
+fn migrate_file(file: &Path) -> Result<()> {
+    let data = std::fs::read(file).with_context(|| format!("读取文件失败: {}", file.display()))?;
+    let version = format_version(&data).with_context(|| file.display().to_string())?;
+    if version == FORMAT_VERSION {
+        println!("{}: 已是最新格式", file.display());
+        return Ok(());
+    }
+
+    let raw = migrate(&data).with_context(|| format!("迁移失败: {}", file.display()))?;
+    replace_file(file, &raw).with_context(|| format!("写入文件失败: {}", file.display()))?;
+    println!("{}: 版本 {version} -> {FORMAT_VERSION}", file.display());
+    Ok(())
+}
+
+/// 先写入同目录下的临时文件再替换原文件, 写入中途失败时原文件保持不变
+///
+/// 原文件为符号链接时替换链接指向的文件, 临时文件沿用原文件的权限
+fn replace_file(file: &Path, data: &[u8]) -> Result<()> {
+    let file = std::fs::canonicalize(file)?;
+    let permissions = std::fs::metadata(&file)?.permissions();
+    let name = file.file_name().context("路径不是文件")?;
+    let mut temp_name = std::ffi::OsString::from(".");
+    temp_name.push(name);
+    temp_name.push(".migrating");
+    let temp = file.with_file_name(temp_name);
 
-    let raw = map(start_path)
-        .inspect_err(|e| eprintln!("错误：{e}"))
-        .expect("映射失败");
-    std::fs::write("map", raw).expect("写入文件失败");
+    let result = (|| -> std::io::Result<()> {
+        let mut out = std::fs::File::create(&temp)?;
+        out.set_permissions(permissions)?;
+        std::io::Write::write_all(&mut out, data)?;
+        out.sync_all()?;
+        std::fs::rename(&temp, &file)
+    })();
+    if result.is_err() {
+        let _ = std::fs::remove_file(&temp);
+    }
+    Ok(result?)
 }
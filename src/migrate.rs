@@ -0,0 +1,61 @@
+//! 旧格式映射的解码与升级
+//!
+//! 每个旧版本在子模块中冻结一份当时的编码结构, 并实现到下一版本的升级 `upgrade`,
+//! 最新的冻结版本直接转换为当前格式, 其余版本的 `into_current` 逐级转发.
+//! 修改当前格式时: 递增 `FORMAT_VERSION`, 把当前结构复制为新的冻结模块,
+//! 再把上一个冻结模块的 `into_current` 改为经由 `upgrade` 转发
+//!
+//! 递归统计只在升级到当前格式时由直接文件重新计算, 中间版本不必维护
+
+use anyhow::{Result, bail};
+
+use crate::{DirMap, FORMAT_VERSION};
+
+mod v0;
+mod v1;
+mod v10;
+mod v11;
+mod v12;
+mod v13;
+mod v14;
+mod v15;
+mod v16;
+mod v17;
+mod v2;
+mod v3;
+mod v4;
+mod v5;
+mod v6;
+mod v7;
+mod v8;
+mod v9;
+
+/// 解码旧版本的映射数据 (已解压), 逐级升级到当前格式
+pub(crate) fn upgrade(version: u16, data: &[u8]) -> Result<DirMap> {
+    match version {
+        0 => v0::decode(data)?.into_current(),
+        1 => v1::decode(data)?.into_current(),
+        2 => v2::decode(data)?.into_current(),
+        3 => v3::decode(data)?.into_current(),
+        4 => v4::decode(data)?.into_current(),
+        5 => v5::decode(data)?.into_current(),
+        6 => v6::decode(data)?.into_current(),
+        7 => v7::decode(data)?.into_current(),
+        8 => v8::decode(data)?.into_current(),
+        9 => v9::decode(data)?.into_current(),
+        10 => v10::decode(data)?.into_current(),
+        11 => v11::decode(data)?.into_current(),
+        12 => v12::decode(data)?.into_current(),
+        13 => v13::decode(data)?.into_current(),
+        14 => v14::decode(data)?.into_current(),
+        15 => v15::decode(data)?.into_current(),
+        16 => v16::decode(data)?.into_current(),
+        17 => v17::decode(data)?.into_current(),
+        _ => bail!("无法升级映射格式版本 {version} 到 {FORMAT_VERSION}"),
+    }
+}
+
+/// 将任意受支持版本的映射文件重写为当前格式
+pub fn migrate(data: &[u8]) -> Result<Vec<u8>> {
+    crate::unmap(data)?.encode()
+}
@@ -0,0 +1,72 @@
+//! 版本 0: 没有映射头, 直接存储 zstd 压缩的 `HashMap<String, Dir>`
+
+use std::collections::{HashMap, HashSet};
+
+use anyhow::Result;
+use bincode::{Decode, config};
+
+use super::v1;
+use crate::DirMap;
+
+#[derive(Decode)]
+pub(super) struct Dir {
+    // 包含子目录的总大小, 升级时重新计算
+    #[allow(dead_code)]
+    pub(super) size: u64,
+    pub(super) file: Vec<File>,
+    pub(super) children: Vec<String>,
+}
+
+#[derive(Decode)]
+pub(super) struct File {
+    pub(super) typ: u8,
+    pub(super) name: String,
+    pub(super) size: u64,
+}
+
+pub(super) struct Snapshot {
+    dirs: HashMap<String, Dir>,
+}
+
+pub(super) fn decode(data: &[u8]) -> Result<Snapshot> {
+    let (dirs, _) = bincode::decode_from_slice(data, config::standard())?;
+    Ok(Snapshot { dirs })
+}
+
+impl Snapshot {
+    pub(super) fn into_current(self) -> Result<DirMap> {
+        self.upgrade().into_current()
+    }
+
+    fn upgrade(self) -> v1::Snapshot {
+        // 旧文件缺失的信息留空
+        let header = v1::Header {
+            root: self.guess_root(),
+            scanned_at: 0,
+            hostname: String::new(),
+            version: String::new(),
+            options: v1::ScanOptions {},
+        };
+        v1::Snapshot {
+            header,
+            dirs: self.dirs,
+        }
+    }
+
+    /// 旧格式未记录根目录, 取不是任何目录子目录的路径, 有多个时取最短的
+    fn guess_root(&self) -> String {
+        let children: HashSet<_> = self
+            .dirs
+            .values()
+            .flat_map(|dir| &dir.children)
+            .map(String::as_str)
+            .collect();
+        self.dirs
+            .keys()
+            .filter(|path| !children.contains(path.as_str()))
+            .min_by_key(|path| path.len())
+            .or_else(|| self.dirs.keys().min_by_key(|path| path.len()))
+            .cloned()
+            .unwrap_or_default()
+    }
+}
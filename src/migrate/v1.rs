@@ -0,0 +1,58 @@
+//! 版本 1: 增加映射头, `Dir::size` 为包含子目录的总大小
+
+use std::collections::HashMap;
+
+use anyhow::Result;
+use bincode::{Decode, config};
+
+use super::{v0::Dir, v2};
+use crate::DirMap;
+
+#[derive(Decode)]
+pub(super) struct ScanOptions {}
+
+#[derive(Decode)]
+pub(super) struct Header {
+    pub(super) root: String,
+    pub(super) scanned_at: u64,
+    pub(super) hostname: String,
+    pub(super) version: String,
+    pub(super) options: ScanOptions,
+}
+
+pub(super) struct Snapshot {
+    pub(super) header: Header,
+    pub(super) dirs: HashMap<String, Dir>,
+}
+
+pub(super) fn decode(data: &[u8]) -> Result<Snapshot> {
+    let ((header, dirs), _) = bincode::decode_from_slice(data, config::standard())?;
+    Ok(Snapshot { header, dirs })
+}
+
+impl Snapshot {
+    pub(super) fn into_current(self) -> Result<DirMap> {
+        self.upgrade().into_current()
+    }
+
+    fn upgrade(self) -> v2::Snapshot {
+        // 递归统计在升级到当前格式时统一重新计算, 这里只保留直接文件的大小
+        let dirs = self
+            .dirs
+            .into_iter()
+            .map(|(path, dir)| {
+                let dir = v2::Dir {
+                    own_size: dir.file.iter().map(|f| f.size).sum(),
+                    total: v2::Totals::default(),
+                    file: dir.file,
+                    children: dir.children,
+                };
+                (path, dir)
+            })
+            .collect();
+        v2::Snapshot {
+            header: self.header,
+            dirs,
+        }
+    }
+}
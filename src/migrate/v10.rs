@@ -0,0 +1,64 @@
+//! 版本 10: 目录按编号存储, 目录名与文件名存入名称表
+
+use anyhow::Result;
+use bincode::{Decode, config};
+
+use super::{
+    v3::ScanError,
+    v4::Link,
+    v5::HardLink,
+    v9::{Boundary, Header, Totals},
+    v11,
+};
+use crate::DirMap;
+
+#[derive(Decode)]
+pub(super) struct FileRecord {
+    pub(super) typ: u8,
+    pub(super) name: u32,
+    pub(super) size: u64,
+    pub(super) disk_size: u64,
+    pub(super) hardlink: Option<HardLink>,
+}
+
+#[derive(Decode)]
+pub(super) struct DirRecord {
+    pub(super) name: u32,
+    pub(super) files: Vec<FileRecord>,
+    pub(super) children: Vec<u32>,
+    pub(super) links: Vec<Link>,
+    pub(super) boundary: Option<Boundary>,
+    pub(super) unexpanded: Totals,
+}
+
+pub(super) struct Snapshot {
+    pub(super) header: Header,
+    pub(super) names: Vec<String>,
+    pub(super) dirs: Vec<DirRecord>,
+    pub(super) errors: Vec<ScanError>,
+}
+
+pub(super) fn decode(data: &[u8]) -> Result<Snapshot> {
+    let ((header, names, dirs, errors), _) = bincode::decode_from_slice(data, config::standard())?;
+    Ok(Snapshot {
+        header,
+        names,
+        dirs,
+        errors,
+    })
+}
+
+impl Snapshot {
+    pub(super) fn into_current(self) -> Result<DirMap> {
+        self.upgrade().into_current()
+    }
+
+    fn upgrade(self) -> v11::Snapshot {
+        v11::Snapshot {
+            header: self.header,
+            names: self.names.into_iter().map(String::into_bytes).collect(),
+            dirs: self.dirs,
+            errors: self.errors,
+        }
+    }
+}
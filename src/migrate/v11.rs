@@ -0,0 +1,71 @@
+//! 版本 11: 名称表保存原始字节, 不是合法 UTF-8 的名称标记为原始名称
+
+use anyhow::Result;
+use bincode::config;
+
+use super::{
+    v3::ScanError,
+    v9::Header,
+    v10::{DirRecord, FileRecord},
+    v12,
+};
+use crate::DirMap;
+
+pub(super) struct Snapshot {
+    pub(super) header: Header,
+    pub(super) names: Vec<Vec<u8>>,
+    pub(super) dirs: Vec<DirRecord>,
+    pub(super) errors: Vec<ScanError>,
+}
+
+pub(super) fn decode(data: &[u8]) -> Result<Snapshot> {
+    let ((header, names, dirs, errors), _) = bincode::decode_from_slice(data, config::standard())?;
+    Ok(Snapshot {
+        header,
+        names,
+        dirs,
+        errors,
+    })
+}
+
+impl FileRecord {
+    fn upgrade(self) -> v12::FileRecord {
+        v12::FileRecord {
+            typ: self.typ,
+            name: self.name,
+            size: self.size,
+            disk_size: self.disk_size,
+            hardlink: self.hardlink,
+            metadata: None,
+        }
+    }
+}
+
+impl DirRecord {
+    fn upgrade(self) -> v12::DirRecord {
+        v12::DirRecord {
+            name: self.name,
+            files: self.files.into_iter().map(FileRecord::upgrade).collect(),
+            children: self.children,
+            links: self.links,
+            boundary: self.boundary,
+            unexpanded: self.unexpanded,
+            metadata: None,
+        }
+    }
+}
+
+impl Snapshot {
+    pub(super) fn into_current(self) -> Result<DirMap> {
+        self.upgrade().into_current()
+    }
+
+    fn upgrade(self) -> v12::Snapshot {
+        v12::Snapshot {
+            header: self.header.upgrade(),
+            names: self.names,
+            dirs: self.dirs.into_iter().map(DirRecord::upgrade).collect(),
+            errors: self.errors,
+        }
+    }
+}
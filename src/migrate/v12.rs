@@ -0,0 +1,176 @@
+//! 版本 12: 可选记录文件与目录的时间戳、权限与属主
+
+use anyhow::Result;
+use bincode::{Decode, config};
+
+use super::{
+    v3::ScanError,
+    v4::{Link, SymlinkPolicy},
+    v5::HardLink,
+    v8::IgnoreFile,
+    v9::{Boundary, Totals},
+    v13,
+};
+use crate::DirMap;
+
+#[derive(Decode)]
+pub(super) struct ScanOptions {
+    pub(super) tolerant: bool,
+    pub(super) symlinks: SymlinkPolicy,
+    pub(super) one_file_system: bool,
+    pub(super) include: Vec<String>,
+    pub(super) exclude: Vec<String>,
+    pub(super) ignore_files: bool,
+    pub(super) max_depth: Option<usize>,
+    pub(super) aggregate: bool,
+    pub(super) metadata: bool,
+}
+
+#[derive(Decode)]
+pub(super) struct Header {
+    pub(super) root: String,
+    pub(super) scanned_at: u64,
+    pub(super) hostname: String,
+    pub(super) version: String,
+    pub(super) options: ScanOptions,
+    pub(super) ignore_rules: Vec<IgnoreFile>,
+}
+
+#[derive(Decode)]
+pub(super) struct Metadata {
+    pub(super) mtime: i64,
+    pub(super) ctime: i64,
+    pub(super) atime: i64,
+    pub(super) mode: u32,
+    pub(super) uid: u32,
+    pub(super) gid: u32,
+}
+
+#[derive(Decode)]
+pub(super) struct FileRecord {
+    pub(super) typ: u8,
+    pub(super) name: u32,
+    pub(super) size: u64,
+    pub(super) disk_size: u64,
+    pub(super) hardlink: Option<HardLink>,
+    pub(super) metadata: Option<Metadata>,
+}
+
+#[derive(Decode)]
+pub(super) struct DirRecord {
+    pub(super) name: u32,
+    pub(super) files: Vec<FileRecord>,
+    pub(super) children: Vec<u32>,
+    pub(super) links: Vec<Link>,
+    pub(super) boundary: Option<Boundary>,
+    pub(super) unexpanded: Totals,
+    pub(super) metadata: Option<Metadata>,
+}
+
+pub(super) struct Snapshot {
+    pub(super) header: Header,
+    pub(super) names: Vec<Vec<u8>>,
+    pub(super) dirs: Vec<DirRecord>,
+    pub(super) errors: Vec<ScanError>,
+}
+
+pub(super) fn decode(data: &[u8]) -> Result<Snapshot> {
+    let ((header, names, dirs, errors), _) = bincode::decode_from_slice(data, config::standard())?;
+    Ok(Snapshot {
+        header,
+        names,
+        dirs,
+        errors,
+    })
+}
+
+impl Header {
+    fn upgrade(self) -> v13::Header {
+        let ScanOptions {
+            tolerant,
+            symlinks,
+            one_file_system,
+            include,
+            exclude,
+            ignore_files,
+            max_depth,
+            aggregate,
+            metadata,
+        } = self.options;
+        v13::Header {
+            root: self.root,
+            scanned_at: self.scanned_at,
+            hostname: self.hostname,
+            version: self.version,
+            options: v13::ScanOptions {
+                tolerant,
+                symlinks,
+                one_file_system,
+                include,
+                exclude,
+                ignore_files,
+                max_depth,
+                aggregate,
+                metadata,
+                hash: v13::HashMode::None,
+            },
+            ignore_rules: self.ignore_rules,
+        }
+    }
+}
+
+impl Metadata {
+    pub(super) fn upgrade(self) -> Box<crate::Metadata> {
+        Box::new(crate::Metadata {
+            mtime: self.mtime,
+            ctime: self.ctime,
+            atime: self.atime,
+            mode: self.mode,
+            uid: self.uid,
+            gid: self.gid,
+        })
+    }
+}
+
+impl FileRecord {
+    fn upgrade(self) -> v13::FileRecord {
+        v13::FileRecord {
+            typ: self.typ,
+            name: self.name,
+            size: self.size,
+            disk_size: self.disk_size,
+            hardlink: self.hardlink,
+            metadata: self.metadata,
+            digest: None,
+        }
+    }
+}
+
+impl DirRecord {
+    fn upgrade(self) -> v13::DirRecord {
+        v13::DirRecord {
+            name: self.name,
+            files: self.files.into_iter().map(FileRecord::upgrade).collect(),
+            children: self.children,
+            links: self.links,
+            boundary: self.boundary,
+            unexpanded: self.unexpanded,
+            metadata: self.metadata,
+        }
+    }
+}
+
+impl Snapshot {
+    pub(super) fn into_current(self) -> Result<DirMap> {
+        self.upgrade().into_current()
+    }
+
+    fn upgrade(self) -> v13::Snapshot {
+        v13::Snapshot {
+            header: self.header.upgrade(),
+            names: self.names,
+            dirs: self.dirs.into_iter().map(DirRecord::upgrade).collect(),
+            errors: self.errors,
+        }
+    }
+}
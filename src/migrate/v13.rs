@@ -0,0 +1,155 @@
+//! 版本 13: 可选记录文件内容摘要
+
+use anyhow::Result;
+use bincode::{Decode, config};
+
+use super::{
+    v3::ScanError,
+    v4::{Link, SymlinkPolicy},
+    v5::HardLink,
+    v8::IgnoreFile,
+    v9::{Boundary, Totals},
+    v12::Metadata,
+    v14,
+};
+use crate::{DirMap, FileKind};
+
+#[derive(Decode)]
+pub(super) struct ScanOptions {
+    pub(super) tolerant: bool,
+    pub(super) symlinks: SymlinkPolicy,
+    pub(super) one_file_system: bool,
+    pub(super) include: Vec<String>,
+    pub(super) exclude: Vec<String>,
+    pub(super) ignore_files: bool,
+    pub(super) max_depth: Option<usize>,
+    pub(super) aggregate: bool,
+    pub(super) metadata: bool,
+    pub(super) hash: HashMode,
+}
+
+#[derive(Decode)]
+pub(super) enum HashMode {
+    None,
+    Duplicates,
+    All,
+}
+
+#[derive(Decode)]
+pub(super) struct Header {
+    pub(super) root: String,
+    pub(super) scanned_at: u64,
+    pub(super) hostname: String,
+    pub(super) version: String,
+    pub(super) options: ScanOptions,
+    pub(super) ignore_rules: Vec<IgnoreFile>,
+}
+
+#[derive(Decode)]
+pub(super) struct FileRecord {
+    pub(super) typ: u8,
+    pub(super) name: u32,
+    pub(super) size: u64,
+    pub(super) disk_size: u64,
+    pub(super) hardlink: Option<HardLink>,
+    pub(super) metadata: Option<Metadata>,
+    pub(super) digest: Option<[u8; 32]>,
+}
+
+#[derive(Decode)]
+pub(super) struct DirRecord {
+    pub(super) name: u32,
+    pub(super) files: Vec<FileRecord>,
+    pub(super) children: Vec<u32>,
+    pub(super) links: Vec<Link>,
+    pub(super) boundary: Option<Boundary>,
+    pub(super) unexpanded: Totals,
+    pub(super) metadata: Option<Metadata>,
+}
+
+pub(super) struct Snapshot {
+    pub(super) header: Header,
+    pub(super) names: Vec<Vec<u8>>,
+    pub(super) dirs: Vec<DirRecord>,
+    pub(super) errors: Vec<ScanError>,
+}
+
+pub(super) fn decode(data: &[u8]) -> Result<Snapshot> {
+    let ((header, names, dirs, errors), _) = bincode::decode_from_slice(data, config::standard())?;
+    Ok(Snapshot {
+        header,
+        names,
+        dirs,
+        errors,
+    })
+}
+
+impl Header {
+    fn upgrade(self) -> v14::Header {
+        let ScanOptions {
+            tolerant,
+            symlinks,
+            one_file_system,
+            include,
+            exclude,
+            ignore_files,
+            max_depth,
+            aggregate,
+            metadata,
+            hash,
+        } = self.options;
+        v14::Header {
+            root: self.root,
+            scanned_at: self.scanned_at,
+            hostname: self.hostname,
+            version: self.version,
+            options: v14::ScanOptions {
+                tolerant,
+                symlinks,
+                one_file_system,
+                include,
+                exclude,
+                ignore_files,
+                max_depth,
+                aggregate,
+                metadata,
+                hash,
+                categories: Vec::new(),
+            },
+            ignore_rules: self.ignore_rules,
+        }
+    }
+}
+
+impl HashMode {
+    pub(super) fn upgrade(self) -> crate::HashMode {
+        match self {
+            HashMode::None => crate::HashMode::None,
+            HashMode::Duplicates => crate::HashMode::Duplicates,
+            HashMode::All => crate::HashMode::All,
+        }
+    }
+}
+
+impl Snapshot {
+    pub(super) fn into_current(self) -> Result<DirMap> {
+        self.upgrade().into_current()
+    }
+
+    fn upgrade(mut self) -> v14::Snapshot {
+        // 此前不认识的类型都记为 "其他" (5), 按新的内置表重新识别
+        for file in self.dirs.iter_mut().flat_map(|dir| &mut dir.files) {
+            if file.typ == 5
+                && let Some(name) = self.names.get(file.name as usize)
+            {
+                file.typ = FileKind::from_name(&String::from_utf8_lossy(name)).code();
+            }
+        }
+        v14::Snapshot {
+            header: self.header.upgrade(),
+            names: self.names,
+            dirs: self.dirs,
+            errors: self.errors,
+        }
+    }
+}
@@ -0,0 +1,144 @@
+//! 版本 14: 扫描选项中记录自定义文件类别表
+
+use anyhow::Result;
+use bincode::{Decode, config};
+
+use super::{
+    v3::ScanError,
+    v4::SymlinkPolicy,
+    v8::IgnoreFile,
+    v13::{DirRecord, FileRecord, HashMode},
+    v15,
+};
+use crate::DirMap;
+
+#[derive(Decode)]
+pub(super) struct ScanOptions {
+    pub(super) tolerant: bool,
+    pub(super) symlinks: SymlinkPolicy,
+    pub(super) one_file_system: bool,
+    pub(super) include: Vec<String>,
+    pub(super) exclude: Vec<String>,
+    pub(super) ignore_files: bool,
+    pub(super) max_depth: Option<usize>,
+    pub(super) aggregate: bool,
+    pub(super) metadata: bool,
+    pub(super) hash: HashMode,
+    pub(super) categories: Vec<CustomCategory>,
+}
+
+#[derive(Decode)]
+pub(super) struct CustomCategory {
+    pub(super) name: String,
+    pub(super) extensions: Vec<String>,
+}
+
+#[derive(Decode)]
+pub(super) struct Header {
+    pub(super) root: String,
+    pub(super) scanned_at: u64,
+    pub(super) hostname: String,
+    pub(super) version: String,
+    pub(super) options: ScanOptions,
+    pub(super) ignore_rules: Vec<IgnoreFile>,
+}
+
+pub(super) struct Snapshot {
+    pub(super) header: Header,
+    pub(super) names: Vec<Vec<u8>>,
+    pub(super) dirs: Vec<DirRecord>,
+    pub(super) errors: Vec<ScanError>,
+}
+
+pub(super) fn decode(data: &[u8]) -> Result<Snapshot> {
+    let ((header, names, dirs, errors), _) = bincode::decode_from_slice(data, config::standard())?;
+    Ok(Snapshot {
+        header,
+        names,
+        dirs,
+        errors,
+    })
+}
+
+impl Header {
+    fn upgrade(self) -> v15::Header {
+        let ScanOptions {
+            tolerant,
+            symlinks,
+            one_file_system,
+            include,
+            exclude,
+            ignore_files,
+            max_depth,
+            aggregate,
+            metadata,
+            hash,
+            categories,
+        } = self.options;
+        v15::Header {
+            root: self.root,
+            scanned_at: self.scanned_at,
+            hostname: self.hostname,
+            version: self.version,
+            options: v15::ScanOptions {
+                tolerant,
+                symlinks,
+                one_file_system,
+                include,
+                exclude,
+                ignore_files,
+                max_depth,
+                aggregate,
+                metadata,
+                hash,
+                sniff: false,
+                categories,
+            },
+            ignore_rules: self.ignore_rules,
+        }
+    }
+}
+
+impl FileRecord {
+    fn upgrade(self) -> v15::FileRecord {
+        v15::FileRecord {
+            typ: self.typ,
+            name: self.name,
+            size: self.size,
+            disk_size: self.disk_size,
+            hardlink: self.hardlink,
+            metadata: self.metadata,
+            digest: self.digest,
+            content: None,
+        }
+    }
+}
+
+impl DirRecord {
+    fn upgrade(self) -> v15::DirRecord {
+        v15::DirRecord {
+            name: self.name,
+            files: self.files.into_iter().map(FileRecord::upgrade).collect(),
+            children: self.children,
+            links: self.links,
+            boundary: self.boundary,
+            unexpanded: self.unexpanded,
+            metadata: self.metadata,
+        }
+    }
+}
+
+impl Snapshot {
+    pub(super) fn into_current(self) -> Result<DirMap> {
+        self.upgrade().into_current()
+    }
+
+    fn upgrade(self) -> v15::Snapshot {
+        v15::Snapshot {
+            header: self.header.upgrade(),
+            names: self.names,
+            dirs: self.dirs.into_iter().map(DirRecord::upgrade).collect(),
+            errors: self.errors,
+        }
+    }
+}
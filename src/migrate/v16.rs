@@ -0,0 +1,195 @@
+//! 版本 16: 可选记录图片尺寸、帧数与 EXIF 信息
+
+use anyhow::Result;
+use bincode::{Decode, config};
+
+use super::{
+    v3::ScanError,
+    v4::{Link, SymlinkPolicy},
+    v5::HardLink,
+    v8::IgnoreFile,
+    v9::{Boundary, Totals},
+    v12::Metadata,
+    v13::HashMode,
+    v14::CustomCategory,
+    v17,
+};
+use crate::DirMap;
+
+#[derive(Decode)]
+pub(super) struct ScanOptions {
+    pub(super) tolerant: bool,
+    pub(super) symlinks: SymlinkPolicy,
+    pub(super) one_file_system: bool,
+    pub(super) include: Vec<String>,
+    pub(super) exclude: Vec<String>,
+    pub(super) ignore_files: bool,
+    pub(super) max_depth: Option<usize>,
+    pub(super) aggregate: bool,
+    pub(super) metadata: bool,
+    pub(super) hash: HashMode,
+    pub(super) sniff: bool,
+    pub(super) images: bool,
+    pub(super) categories: Vec<CustomCategory>,
+}
+
+#[derive(Decode)]
+pub(super) struct Header {
+    pub(super) root: String,
+    pub(super) scanned_at: u64,
+    pub(super) hostname: String,
+    pub(super) version: String,
+    pub(super) options: ScanOptions,
+    pub(super) ignore_rules: Vec<IgnoreFile>,
+}
+
+#[derive(Decode)]
+pub(super) struct FileRecord {
+    pub(super) typ: u8,
+    pub(super) name: u32,
+    pub(super) size: u64,
+    pub(super) disk_size: u64,
+    pub(super) hardlink: Option<HardLink>,
+    pub(super) metadata: Option<Metadata>,
+    pub(super) digest: Option<[u8; 32]>,
+    pub(super) content: Option<u8>,
+    pub(super) image: Option<ImageInfo>,
+}
+
+#[derive(Decode)]
+pub(super) struct ImageInfo {
+    pub(super) width: u32,
+    pub(super) height: u32,
+    pub(super) frames: u32,
+    pub(super) taken_at: Option<i64>,
+    pub(super) orientation: Option<u16>,
+}
+
+#[derive(Decode)]
+pub(super) struct DirRecord {
+    pub(super) name: u32,
+    pub(super) files: Vec<FileRecord>,
+    pub(super) children: Vec<u32>,
+    pub(super) links: Vec<Link>,
+    pub(super) boundary: Option<Boundary>,
+    pub(super) unexpanded: Totals,
+    pub(super) metadata: Option<Metadata>,
+}
+
+pub(super) struct Snapshot {
+    pub(super) header: Header,
+    pub(super) names: Vec<Vec<u8>>,
+    pub(super) dirs: Vec<DirRecord>,
+    pub(super) errors: Vec<ScanError>,
+}
+
+pub(super) fn decode(data: &[u8]) -> Result<Snapshot> {
+    let ((header, names, dirs, errors), _) = bincode::decode_from_slice(data, config::standard())?;
+    Ok(Snapshot {
+        header,
+        names,
+        dirs,
+        errors,
+    })
+}
+
+impl Header {
+    fn upgrade(self) -> v17::Header {
+        let ScanOptions {
+            tolerant,
+            symlinks,
+            one_file_system,
+            include,
+            exclude,
+            ignore_files,
+            max_depth,
+            aggregate,
+            metadata,
+            hash,
+            sniff,
+            images,
+            categories,
+        } = self.options;
+        v17::Header {
+            root: self.root,
+            scanned_at: self.scanned_at,
+            hostname: self.hostname,
+            version: self.version,
+            options: v17::ScanOptions {
+                tolerant,
+                symlinks,
+                one_file_system,
+                include,
+                exclude,
+                ignore_files,
+                max_depth,
+                aggregate,
+                metadata,
+                hash,
+                sniff,
+                images,
+                perceptual: None,
+                categories,
+            },
+            ignore_rules: self.ignore_rules,
+        }
+    }
+}
+
+impl ImageInfo {
+    pub(super) fn upgrade(self) -> Box<crate::ImageInfo> {
+        Box::new(crate::ImageInfo {
+            width: self.width,
+            height: self.height,
+            frames: self.frames,
+            taken_at: self.taken_at,
+            orientation: self.orientation,
+        })
+    }
+}
+
+impl FileRecord {
+    fn upgrade(self) -> v17::FileRecord {
+        v17::FileRecord {
+            typ: self.typ,
+            name: self.name,
+            size: self.size,
+            disk_size: self.disk_size,
+            hardlink: self.hardlink,
+            metadata: self.metadata,
+            digest: self.digest,
+            content: self.content,
+            image: self.image,
+            phash: None,
+        }
+    }
+}
+
+impl DirRecord {
+    fn upgrade(self) -> v17::DirRecord {
+        v17::DirRecord {
+            name: self.name,
+            files: self.files.into_iter().map(FileRecord::upgrade).collect(),
+            children: self.children,
+            links: self.links,
+            boundary: self.boundary,
+            unexpanded: self.unexpanded,
+            metadata: self.metadata,
+        }
+    }
+}
+
+impl Snapshot {
+    pub(super) fn into_current(self) -> Result<DirMap> {
+        self.upgrade().into_current()
+    }
+
+    fn upgrade(self) -> v17::Snapshot {
+        v17::Snapshot {
+            header: self.header.upgrade(),
+            names: self.names,
+            dirs: self.dirs.into_iter().map(DirRecord::upgrade).collect(),
+            errors: self.errors,
+        }
+    }
+}
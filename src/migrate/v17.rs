@@ -0,0 +1,248 @@
+//! 版本 17: 可选计算图片感知哈希
+
+use std::sync::Arc;
+
+use anyhow::{Result, anyhow};
+use bincode::{Decode, config};
+
+use super::{
+    v3::ScanError,
+    v4::{Link, SymlinkPolicy},
+    v5::HardLink,
+    v8::IgnoreFile,
+    v9::{Boundary, Totals},
+    v12::Metadata,
+    v13::HashMode,
+    v14::CustomCategory,
+    v16::ImageInfo,
+};
+use crate::{Digest, DirId, DirMap, name};
+
+#[derive(Decode)]
+pub(super) struct ScanOptions {
+    pub(super) tolerant: bool,
+    pub(super) symlinks: SymlinkPolicy,
+    pub(super) one_file_system: bool,
+    pub(super) include: Vec<String>,
+    pub(super) exclude: Vec<String>,
+    pub(super) ignore_files: bool,
+    pub(super) max_depth: Option<usize>,
+    pub(super) aggregate: bool,
+    pub(super) metadata: bool,
+    pub(super) hash: HashMode,
+    pub(super) sniff: bool,
+    pub(super) images: bool,
+    pub(super) perceptual: Option<PerceptualHash>,
+    pub(super) categories: Vec<CustomCategory>,
+}
+
+#[derive(Decode)]
+pub(super) enum PerceptualHash {
+    Average,
+    Difference,
+    Dct,
+}
+
+#[derive(Decode)]
+pub(super) struct Header {
+    pub(super) root: String,
+    pub(super) scanned_at: u64,
+    pub(super) hostname: String,
+    pub(super) version: String,
+    pub(super) options: ScanOptions,
+    pub(super) ignore_rules: Vec<IgnoreFile>,
+}
+
+#[derive(Decode)]
+pub(super) struct FileRecord {
+    pub(super) typ: u8,
+    pub(super) name: u32,
+    pub(super) size: u64,
+    pub(super) disk_size: u64,
+    pub(super) hardlink: Option<HardLink>,
+    pub(super) metadata: Option<Metadata>,
+    pub(super) digest: Option<[u8; 32]>,
+    pub(super) content: Option<u8>,
+    pub(super) image: Option<ImageInfo>,
+    pub(super) phash: Option<u64>,
+}
+
+#[derive(Decode)]
+pub(super) struct DirRecord {
+    pub(super) name: u32,
+    pub(super) files: Vec<FileRecord>,
+    pub(super) children: Vec<u32>,
+    pub(super) links: Vec<Link>,
+    pub(super) boundary: Option<Boundary>,
+    pub(super) unexpanded: Totals,
+    pub(super) metadata: Option<Metadata>,
+}
+
+pub(super) struct Snapshot {
+    pub(super) header: Header,
+    pub(super) names: Vec<Vec<u8>>,
+    pub(super) dirs: Vec<DirRecord>,
+    pub(super) errors: Vec<ScanError>,
+}
+
+pub(super) fn decode(data: &[u8]) -> Result<Snapshot> {
+    let ((header, names, dirs, errors), _) = bincode::decode_from_slice(data, config::standard())?;
+    Ok(Snapshot {
+        header,
+        names,
+        dirs,
+        errors,
+    })
+}
+
+impl Header {
+    fn upgrade(self) -> crate::Header {
+        let ScanOptions {
+            tolerant,
+            symlinks,
+            one_file_system,
+            include,
+            exclude,
+            ignore_files,
+            max_depth,
+            aggregate,
+            metadata,
+            hash,
+            sniff,
+            images,
+            perceptual,
+            categories,
+        } = self.options;
+        crate::Header {
+            root: self.root.clone(),
+            scanned_at: self.scanned_at,
+            hostname: self.hostname,
+            version: self.version,
+            options: crate::ScanOptions {
+                tolerant,
+                symlinks: symlinks.upgrade(),
+                one_file_system,
+                include,
+                exclude,
+                ignore_files,
+                max_depth,
+                aggregate,
+                metadata,
+                hash: hash.upgrade(),
+                sniff,
+                images,
+                perceptual: perceptual.map(PerceptualHash::upgrade),
+                categories: categories
+                    .into_iter()
+                    .map(|category| crate::CustomCategory {
+                        name: category.name,
+                        extensions: category.extensions,
+                    })
+                    .collect(),
+            },
+            ignore_rules: self
+                .ignore_rules
+                .into_iter()
+                .map(|file| crate::IgnoreFile {
+                    path: relative_path(&self.root, file.path),
+                    rules: file.rules,
+                })
+                .collect(),
+        }
+    }
+}
+
+impl PerceptualHash {
+    fn upgrade(self) -> crate::PerceptualHash {
+        match self {
+            PerceptualHash::Average => crate::PerceptualHash::Average,
+            PerceptualHash::Difference => crate::PerceptualHash::Difference,
+            PerceptualHash::Dct => crate::PerceptualHash::Dct,
+        }
+    }
+}
+
+impl Snapshot {
+    pub(super) fn into_current(self) -> Result<DirMap> {
+        let names: Vec<(Arc<str>, bool)> = self
+            .names
+            .into_iter()
+            .map(|bytes| {
+                let (name, raw) = name::from_bytes(bytes);
+                (Arc::from(name), raw)
+            })
+            .collect();
+        let lookup = |index: u32| {
+            names
+                .get(index as usize)
+                .cloned()
+                .ok_or_else(|| anyhow!("映射文件损坏: 名称编号越界: {index}"))
+        };
+
+        let mut dirs = Vec::with_capacity(self.dirs.len());
+        for record in self.dirs {
+            let (name, raw) = lookup(record.name)?;
+            let mut dir = crate::Dir {
+                name,
+                raw,
+                children: record.children.into_iter().map(DirId).collect(),
+                links: record.links.into_iter().map(Link::upgrade).collect(),
+                boundary: record.boundary.map(Boundary::upgrade),
+                unexpanded: record.unexpanded.upgrade(),
+                metadata: record.metadata.map(Metadata::upgrade),
+                ..Default::default()
+            };
+            for file in record.files {
+                let (name, raw) = lookup(file.name)?;
+                dir.push_file(crate::File {
+                    typ: file.typ,
+                    raw,
+                    name,
+                    size: file.size,
+                    disk_size: file.disk_size,
+                    hardlink: file.hardlink.map(HardLink::upgrade),
+                    metadata: file.metadata.map(Metadata::upgrade),
+                    digest: file.digest.map(|bytes| Box::new(Digest(bytes))),
+                    content: file.content,
+                    image: file.image.map(ImageInfo::upgrade),
+                    phash: file.phash,
+                });
+            }
+            dirs.push(dir);
+        }
+
+        for index in 0..dirs.len() {
+            for child in dirs[index].children.clone() {
+                let dir: &mut crate::Dir = dirs
+                    .get_mut(child.index())
+                    .filter(|dir| dir.parent.is_none() && child != DirId::ROOT)
+                    .ok_or_else(|| anyhow!("映射文件损坏: 子目录编号无效: {}", child.0))?;
+                dir.parent = Some(DirId(index as u32));
+            }
+        }
+
+        let errors = self
+            .errors
+            .into_iter()
+            .map(|err| {
+                let err = err.upgrade();
+                crate::ScanError::new(
+                    relative_path(&self.header.root, err.path().to_string()),
+                    err.kind(),
+                    err.message().to_string(),
+                )
+            })
+            .collect();
+        DirMap::with_totals(self.header.upgrade(), dirs, errors)
+    }
+}
+
+/// 版本 17 及以前的错误与忽略文件路径以扫描根目录开头, 改为相对根目录
+fn relative_path(root: &str, path: String) -> String {
+    match path.strip_prefix(root) {
+        Some(rest) if rest.is_empty() || rest.starts_with('/') || root.ends_with('/') => {
+            rest.trim_start_matches('/').to_string()
+        }
+        _ => path,
+    }
+}
@@ -0,0 +1,69 @@
+//! 版本 2: `Dir` 分别记录直接文件大小与递归统计
+
+use std::collections::HashMap;
+
+use anyhow::Result;
+use bincode::{Decode, config};
+
+use super::{
+    v0::File,
+    v1::{Header, ScanOptions},
+    v3,
+};
+use crate::DirMap;
+
+#[derive(Decode, Default)]
+pub(super) struct Totals {
+    _size: u64,
+    _files: u64,
+    _dirs: u64,
+}
+
+#[derive(Decode)]
+pub(super) struct Dir {
+    pub(super) own_size: u64,
+    // 升级时重新计算
+    #[allow(dead_code)]
+    pub(super) total: Totals,
+    pub(super) file: Vec<File>,
+    pub(super) children: Vec<String>,
+}
+
+pub(super) struct Snapshot {
+    pub(super) header: Header,
+    pub(super) dirs: HashMap<String, Dir>,
+}
+
+pub(super) fn decode(data: &[u8]) -> Result<Snapshot> {
+    let ((header, dirs), _) = bincode::decode_from_slice(data, config::standard())?;
+    Ok(Snapshot { header, dirs })
+}
+
+impl Snapshot {
+    pub(super) fn into_current(self) -> Result<DirMap> {
+        self.upgrade().into_current()
+    }
+
+    fn upgrade(self) -> v3::Snapshot {
+        let Header {
+            root,
+            scanned_at,
+            hostname,
+            version,
+            options: ScanOptions {},
+        } = self.header;
+        let header = v3::Header {
+            root,
+            scanned_at,
+            hostname,
+            version,
+            // 旧版本只支持严格扫描
+            options: v3::ScanOptions { tolerant: false },
+        };
+        v3::Snapshot {
+            header,
+            dirs: self.dirs,
+            errors: Vec::new(),
+        }
+    }
+}
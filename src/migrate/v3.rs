@@ -0,0 +1,112 @@
+//! 版本 3: 映射中记录容错扫描跳过的路径
+
+use std::collections::HashMap;
+
+use anyhow::Result;
+use bincode::{Decode, config};
+
+use super::{v2::Dir, v4};
+use crate::DirMap;
+
+#[derive(Decode)]
+pub(super) struct ScanOptions {
+    pub(super) tolerant: bool,
+}
+
+#[derive(Decode)]
+pub(super) struct Header {
+    pub(super) root: String,
+    pub(super) scanned_at: u64,
+    pub(super) hostname: String,
+    pub(super) version: String,
+    pub(super) options: ScanOptions,
+}
+
+#[derive(Decode)]
+pub(super) enum ErrorKind {
+    PermissionDenied,
+    NotFound,
+    Loop,
+    Other,
+}
+
+#[derive(Decode)]
+pub(super) struct ScanError {
+    pub(super) path: String,
+    pub(super) kind: ErrorKind,
+    pub(super) message: String,
+}
+
+pub(super) struct Snapshot {
+    pub(super) header: Header,
+    pub(super) dirs: HashMap<String, Dir>,
+    pub(super) errors: Vec<ScanError>,
+}
+
+pub(super) fn decode(data: &[u8]) -> Result<Snapshot> {
+    let ((header, dirs, errors), _) = bincode::decode_from_slice(data, config::standard())?;
+    Ok(Snapshot {
+        header,
+        dirs,
+        errors,
+    })
+}
+
+impl ScanError {
+    pub(super) fn upgrade(self) -> crate::ScanError {
+        let kind = match self.kind {
+            ErrorKind::PermissionDenied => crate::ErrorKind::PermissionDenied,
+            ErrorKind::NotFound => crate::ErrorKind::NotFound,
+            ErrorKind::Loop => crate::ErrorKind::Loop,
+            ErrorKind::Other => crate::ErrorKind::Other,
+        };
+        crate::ScanError::new(self.path, kind, self.message)
+    }
+}
+
+impl Snapshot {
+    pub(super) fn into_current(self) -> Result<DirMap> {
+        self.upgrade().into_current()
+    }
+
+    fn upgrade(self) -> v4::Snapshot {
+        let Header {
+            root,
+            scanned_at,
+            hostname,
+            version,
+            options: ScanOptions { tolerant },
+        } = self.header;
+        let header = v4::Header {
+            root,
+            scanned_at,
+            hostname,
+            version,
+            // 旧版本不记录符号链接
+            options: v4::ScanOptions {
+                tolerant,
+                symlinks: v4::SymlinkPolicy::Skip,
+            },
+        };
+
+        let dirs = self
+            .dirs
+            .into_iter()
+            .map(|(path, dir)| {
+                let dir = v4::Dir {
+                    own_size: dir.own_size,
+                    total: v4::Totals::default(),
+                    file: dir.file,
+                    children: dir.children,
+                    links: Vec::new(),
+                };
+                (path, dir)
+            })
+            .collect();
+        v4::Snapshot {
+            header,
+            dirs,
+            errors: self.errors,
+        }
+    }
+}
@@ -0,0 +1,136 @@
+//! 版本 4: 增加符号链接策略与链接条目
+
+use std::collections::HashMap;
+
+use anyhow::Result;
+use bincode::{Decode, config};
+
+use super::{v0::File, v3::ScanError, v5, v7};
+use crate::DirMap;
+
+#[derive(Decode)]
+pub(super) enum SymlinkPolicy {
+    Skip,
+    Record,
+    Follow,
+}
+
+#[derive(Decode)]
+pub(super) struct ScanOptions {
+    pub(super) tolerant: bool,
+    pub(super) symlinks: SymlinkPolicy,
+}
+
+#[derive(Decode)]
+pub(super) struct Header {
+    pub(super) root: String,
+    pub(super) scanned_at: u64,
+    pub(super) hostname: String,
+    pub(super) version: String,
+    pub(super) options: ScanOptions,
+}
+
+#[derive(Decode, Default)]
+pub(super) struct Totals {
+    _size: u64,
+    _files: u64,
+    _dirs: u64,
+    _links: u64,
+}
+
+#[derive(Decode)]
+pub(super) struct Link {
+    pub(super) name: String,
+    pub(super) target: String,
+}
+
+#[derive(Decode)]
+pub(super) struct Dir {
+    pub(super) own_size: u64,
+    // 升级时重新计算
+    #[allow(dead_code)]
+    pub(super) total: Totals,
+    pub(super) file: Vec<File>,
+    pub(super) children: Vec<String>,
+    pub(super) links: Vec<Link>,
+}
+
+pub(super) struct Snapshot {
+    pub(super) header: Header,
+    pub(super) dirs: HashMap<String, Dir>,
+    pub(super) errors: Vec<ScanError>,
+}
+
+pub(super) fn decode(data: &[u8]) -> Result<Snapshot> {
+    let ((header, dirs, errors), _) = bincode::decode_from_slice(data, config::standard())?;
+    Ok(Snapshot {
+        header,
+        dirs,
+        errors,
+    })
+}
+
+impl SymlinkPolicy {
+    pub(super) fn upgrade(self) -> crate::SymlinkPolicy {
+        match self {
+            SymlinkPolicy::Skip => crate::SymlinkPolicy::Skip,
+            SymlinkPolicy::Record => crate::SymlinkPolicy::Record,
+            SymlinkPolicy::Follow => crate::SymlinkPolicy::Follow,
+        }
+    }
+}
+
+impl Header {
+    pub(super) fn upgrade(self) -> v7::Header {
+        let ScanOptions { tolerant, symlinks } = self.options;
+        v7::Header {
+            root: self.root,
+            scanned_at: self.scanned_at,
+            hostname: self.hostname,
+            version: self.version,
+            options: v7::ScanOptions {
+                tolerant,
+                symlinks,
+                one_file_system: false,
+            },
+        }
+    }
+}
+
+impl Link {
+    pub(super) fn upgrade(self) -> crate::Link {
+        // 旧格式的链接名与目标没有转义
+        crate::Link {
+            name: self.name.replace('\\', "\\\\"),
+            target: self.target.replace('\\', "\\\\"),
+        }
+    }
+}
+
+impl Snapshot {
+    pub(super) fn into_current(self) -> Result<DirMap> {
+        self.upgrade().into_current()
+    }
+
+    fn upgrade(self) -> v5::Snapshot {
+        let dirs = self
+            .dirs
+            .into_iter()
+            .map(|(path, dir)| {
+                let dir = v5::Dir {
+                    own_size: dir.own_size,
+                    total: Totals::default(),
+                    file: dir.file.into_iter().map(v5::File::from).collect(),
+                    children: dir.children,
+                    links: dir.links,
+                };
+                (path, dir)
+            })
+            .collect();
+        v5::Snapshot {
+            header: self.header,
+            dirs,
+            errors: self.errors,
+        }
+    }
+}
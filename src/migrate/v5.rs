@@ -0,0 +1,116 @@
+//! 版本 5: 文件记录硬链接信息
+
+use std::collections::HashMap;
+
+use anyhow::Result;
+use bincode::{Decode, config};
+
+use super::{
+    v0,
+    v3::ScanError,
+    v4::{Header, Link, Totals},
+    v6,
+};
+use crate::DirMap;
+
+#[derive(Decode)]
+pub(super) struct HardLink {
+    dev: u64,
+    ino: u64,
+    primary: bool,
+}
+
+#[derive(Decode)]
+pub(super) struct File {
+    typ: u8,
+    name: String,
+    size: u64,
+    hardlink: Option<HardLink>,
+}
+
+#[derive(Decode)]
+pub(super) struct Dir {
+    pub(super) own_size: u64,
+    // 升级时重新计算
+    #[allow(dead_code)]
+    pub(super) total: Totals,
+    pub(super) file: Vec<File>,
+    pub(super) children: Vec<String>,
+    pub(super) links: Vec<Link>,
+}
+
+pub(super) struct Snapshot {
+    pub(super) header: Header,
+    pub(super) dirs: HashMap<String, Dir>,
+    pub(super) errors: Vec<ScanError>,
+}
+
+pub(super) fn decode(data: &[u8]) -> Result<Snapshot> {
+    let ((header, dirs, errors), _) = bincode::decode_from_slice(data, config::standard())?;
+    Ok(Snapshot {
+        header,
+        dirs,
+        errors,
+    })
+}
+
+impl From<v0::File> for File {
+    fn from(file: v0::File) -> Self {
+        File {
+            typ: file.typ,
+            name: file.name,
+            size: file.size,
+            hardlink: None,
+        }
+    }
+}
+
+impl HardLink {
+    pub(super) fn upgrade(self) -> crate::HardLink {
+        crate::HardLink {
+            dev: self.dev,
+            ino: self.ino,
+            primary: self.primary,
+        }
+    }
+}
+
+impl Snapshot {
+    pub(super) fn into_current(self) -> Result<DirMap> {
+        self.upgrade().into_current()
+    }
+
+    fn upgrade(self) -> v6::Snapshot {
+        let dirs = self
+            .dirs
+            .into_iter()
+            .map(|(path, dir)| {
+                // 旧版本未记录磁盘占用, 按表观大小计
+                let dir = v6::Dir {
+                    own_size: dir.own_size,
+                    own_disk_size: dir.own_size,
+                    total: v6::Totals::default(),
+                    file: dir
+                        .file
+                        .into_iter()
+                        .map(|file| v6::File {
+                            typ: file.typ,
+                            name: file.name,
+                            size: file.size,
+                            disk_size: file.size,
+                            hardlink: file.hardlink,
+                        })
+                        .collect(),
+                    children: dir.children,
+                    links: dir.links,
+                };
+                (path, dir)
+            })
+            .collect();
+        v6::Snapshot {
+            header: self.header,
+            dirs,
+            errors: self.errors,
+        }
+    }
+}
@@ -0,0 +1,89 @@
+//! 版本 6: 文件与目录记录磁盘占用
+
+use std::collections::HashMap;
+
+use anyhow::Result;
+use bincode::{Decode, config};
+
+use super::{
+    v3::ScanError,
+    v4::{Header, Link},
+    v5::HardLink,
+    v7,
+};
+use crate::DirMap;
+
+#[derive(Decode)]
+pub(super) struct File {
+    pub(super) typ: u8,
+    pub(super) name: String,
+    pub(super) size: u64,
+    pub(super) disk_size: u64,
+    pub(super) hardlink: Option<HardLink>,
+}
+
+#[derive(Decode, Default)]
+pub(super) struct Totals {
+    _size: u64,
+    _disk_size: u64,
+    _files: u64,
+    _dirs: u64,
+    _links: u64,
+}
+
+#[derive(Decode)]
+pub(super) struct Dir {
+    pub(super) own_size: u64,
+    pub(super) own_disk_size: u64,
+    // 升级时重新计算
+    #[allow(dead_code)]
+    pub(super) total: Totals,
+    pub(super) file: Vec<File>,
+    pub(super) children: Vec<String>,
+    pub(super) links: Vec<Link>,
+}
+
+pub(super) struct Snapshot {
+    pub(super) header: Header,
+    pub(super) dirs: HashMap<String, Dir>,
+    pub(super) errors: Vec<ScanError>,
+}
+
+pub(super) fn decode(data: &[u8]) -> Result<Snapshot> {
+    let ((header, dirs, errors), _) = bincode::decode_from_slice(data, config::standard())?;
+    Ok(Snapshot {
+        header,
+        dirs,
+        errors,
+    })
+}
+
+impl Snapshot {
+    pub(super) fn into_current(self) -> Result<DirMap> {
+        self.upgrade().into_current()
+    }
+
+    fn upgrade(self) -> v7::Snapshot {
+        let dirs = self
+            .dirs
+            .into_iter()
+            .map(|(path, dir)| {
+                let dir = v7::Dir {
+                    own_size: dir.own_size,
+                    own_disk_size: dir.own_disk_size,
+                    total: Totals::default(),
+                    file: dir.file,
+                    children: dir.children,
+                    links: dir.links,
+                    boundary: None,
+                };
+                (path, dir)
+            })
+            .collect();
+        v7::Snapshot {
+            header: self.header.upgrade(),
+            dirs,
+            errors: self.errors,
+        }
+    }
+}
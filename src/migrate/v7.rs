@@ -0,0 +1,119 @@
+//! 版本 7: 不跨越文件系统, 挂载点记录为未展开的目录
+
+use std::collections::HashMap;
+
+use anyhow::Result;
+use bincode::{Decode, config};
+
+use super::{
+    v3::ScanError,
+    v4::{Link, SymlinkPolicy},
+    v6::{File, Totals},
+    v8, v9,
+};
+use crate::DirMap;
+
+#[derive(Decode)]
+pub(super) struct ScanOptions {
+    pub(super) tolerant: bool,
+    pub(super) symlinks: SymlinkPolicy,
+    pub(super) one_file_system: bool,
+}
+
+#[derive(Decode)]
+pub(super) struct Header {
+    pub(super) root: String,
+    pub(super) scanned_at: u64,
+    pub(super) hostname: String,
+    pub(super) version: String,
+    pub(super) options: ScanOptions,
+}
+
+#[derive(Decode)]
+pub(super) enum Boundary {
+    MountPoint,
+}
+
+#[derive(Decode)]
+pub(super) struct Dir {
+    pub(super) own_size: u64,
+    pub(super) own_disk_size: u64,
+    // 升级时重新计算
+    #[allow(dead_code)]
+    pub(super) total: Totals,
+    pub(super) file: Vec<File>,
+    pub(super) children: Vec<String>,
+    pub(super) links: Vec<Link>,
+    pub(super) boundary: Option<Boundary>,
+}
+
+pub(super) struct Snapshot {
+    pub(super) header: Header,
+    pub(super) dirs: HashMap<String, Dir>,
+    pub(super) errors: Vec<ScanError>,
+}
+
+pub(super) fn decode(data: &[u8]) -> Result<Snapshot> {
+    let ((header, dirs, errors), _) = bincode::decode_from_slice(data, config::standard())?;
+    Ok(Snapshot {
+        header,
+        dirs,
+        errors,
+    })
+}
+
+impl Header {
+    fn upgrade(self) -> v8::Header {
+        let ScanOptions {
+            tolerant,
+            symlinks,
+            one_file_system,
+        } = self.options;
+        v8::Header {
+            root: self.root,
+            scanned_at: self.scanned_at,
+            hostname: self.hostname,
+            version: self.version,
+            options: v8::ScanOptions {
+                tolerant,
+                symlinks,
+                one_file_system,
+                include: Vec::new(),
+                exclude: Vec::new(),
+                ignore_files: false,
+            },
+            ignore_rules: Vec::new(),
+        }
+    }
+}
+
+impl Dir {
+    pub(super) fn upgrade(self) -> v9::Dir {
+        v9::Dir {
+            own_size: self.own_size,
+            own_disk_size: self.own_disk_size,
+            total: v9::Totals::default(),
+            file: self.file,
+            children: self.children,
+            links: self.links,
+            boundary: self
+                .boundary
+                .map(|Boundary::MountPoint| v9::Boundary::MountPoint),
+            unexpanded: v9::Totals::default(),
+        }
+    }
+}
+
+impl Snapshot {
+    pub(super) fn into_current(self) -> Result<DirMap> {
+        self.upgrade().into_current()
+    }
+
+    fn upgrade(self) -> v8::Snapshot {
+        v8::Snapshot {
+            header: self.header.upgrade(),
+            dirs: self.dirs,
+            errors: self.errors,
+        }
+    }
+}
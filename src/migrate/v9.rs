@@ -0,0 +1,215 @@
+//! 版本 9: 限制扫描深度, 达到深度的目录可汇总未展开内容的统计
+
+use std::collections::HashMap;
+
+use anyhow::{Result, anyhow};
+use bincode::{Decode, config};
+
+use super::{
+    v3::ScanError,
+    v4::{Link, SymlinkPolicy},
+    v6::File,
+    v8::IgnoreFile,
+    v10, v12,
+};
+use crate::DirMap;
+
+#[derive(Decode)]
+pub(super) struct ScanOptions {
+    pub(super) tolerant: bool,
+    pub(super) symlinks: SymlinkPolicy,
+    pub(super) one_file_system: bool,
+    pub(super) include: Vec<String>,
+    pub(super) exclude: Vec<String>,
+    pub(super) ignore_files: bool,
+    pub(super) max_depth: Option<usize>,
+    pub(super) aggregate: bool,
+}
+
+#[derive(Decode)]
+pub(super) struct Header {
+    pub(super) root: String,
+    pub(super) scanned_at: u64,
+    pub(super) hostname: String,
+    pub(super) version: String,
+    pub(super) options: ScanOptions,
+    pub(super) ignore_rules: Vec<IgnoreFile>,
+}
+
+#[derive(Decode)]
+pub(super) enum Boundary {
+    MountPoint,
+    MaxDepth,
+}
+
+#[derive(Decode, Default)]
+pub(super) struct Totals {
+    pub(super) size: u64,
+    pub(super) disk_size: u64,
+    pub(super) files: u64,
+    pub(super) dirs: u64,
+    pub(super) links: u64,
+}
+
+#[derive(Decode)]
+pub(super) struct Dir {
+    // 升级时由直接文件重新计算
+    #[allow(dead_code)]
+    pub(super) own_size: u64,
+    #[allow(dead_code)]
+    pub(super) own_disk_size: u64,
+    // 升级时重新计算
+    #[allow(dead_code)]
+    pub(super) total: Totals,
+    pub(super) file: Vec<File>,
+    pub(super) children: Vec<String>,
+    pub(super) links: Vec<Link>,
+    pub(super) boundary: Option<Boundary>,
+    pub(super) unexpanded: Totals,
+}
+
+pub(super) struct Snapshot {
+    pub(super) header: Header,
+    pub(super) dirs: HashMap<String, Dir>,
+    pub(super) errors: Vec<ScanError>,
+}
+
+pub(super) fn decode(data: &[u8]) -> Result<Snapshot> {
+    let ((header, dirs, errors), _) = bincode::decode_from_slice(data, config::standard())?;
+    Ok(Snapshot {
+        header,
+        dirs,
+        errors,
+    })
+}
+
+impl Header {
+    /// 版本 10 与 11 沿用本版本的映射头
+    pub(super) fn upgrade(self) -> v12::Header {
+        let ScanOptions {
+            tolerant,
+            symlinks,
+            one_file_system,
+            include,
+            exclude,
+            ignore_files,
+            max_depth,
+            aggregate,
+        } = self.options;
+        v12::Header {
+            root: self.root,
+            scanned_at: self.scanned_at,
+            hostname: self.hostname,
+            version: self.version,
+            options: v12::ScanOptions {
+                tolerant,
+                symlinks,
+                one_file_system,
+                include,
+                exclude,
+                ignore_files,
+                max_depth,
+                aggregate,
+                metadata: false,
+            },
+            ignore_rules: self.ignore_rules,
+        }
+    }
+}
+
+impl Totals {
+    pub(super) fn upgrade(self) -> crate::Totals {
+        crate::Totals {
+            size: self.size,
+            disk_size: self.disk_size,
+            files: self.files,
+            dirs: self.dirs,
+            links: self.links,
+        }
+    }
+}
+
+impl Boundary {
+    pub(super) fn upgrade(self) -> crate::Boundary {
+        match self {
+            Boundary::MountPoint => crate::Boundary::MountPoint,
+            Boundary::MaxDepth => crate::Boundary::MaxDepth,
+        }
+    }
+}
+
+/// 升级时构建的名称表
+#[derive(Default)]
+struct Names {
+    index: HashMap<String, u32>,
+    names: Vec<String>,
+}
+
+impl Names {
+    fn intern(&mut self, name: &str) -> u32 {
+        if let Some(&index) = self.index.get(name) {
+            return index;
+        }
+        let index = self.names.len() as u32;
+        self.index.insert(name.to_string(), index);
+        self.names.push(name.to_string());
+        index
+    }
+}
+
+impl Snapshot {
+    pub(super) fn into_current(self) -> Result<DirMap> {
+        self.upgrade()?.into_current()
+    }
+
+    /// 从根目录开始按深度优先顺序编号, 目录名取路径的最后一级
+    fn upgrade(mut self) -> Result<v10::Snapshot> {
+        let mut names = Names::default();
+        let mut records: Vec<v10::DirRecord> = Vec::with_capacity(self.dirs.len());
+        let mut stack: Vec<(String, Option<usize>)> = vec![(self.header.root.clone(), None)];
+        while let Some((path, parent)) = stack.pop() {
+            let dir = self
+                .dirs
+                .remove(&path)
+                .ok_or_else(|| anyhow!("目录不存在: {path}"))?;
+            let id = records.len();
+            let name = match parent {
+                Some(parent) => {
+                    records[parent].children.push(id as u32);
+                    path.rsplit_once('/').map_or(&*path, |(_, name)| name)
+                }
+                None => &path,
+            };
+            stack.extend(
+                dir.children
+                    .iter()
+                    .rev()
+                    .map(|child| (child.clone(), Some(id))),
+            );
+            records.push(v10::DirRecord {
+                name: names.intern(name),
+                files: dir
+                    .file
+                    .into_iter()
+                    .map(|file| v10::FileRecord {
+                        typ: file.typ,
+                        name: names.intern(&file.name),
+                        size: file.size,
+                        disk_size: file.disk_size,
+                        hardlink: file.hardlink,
+                    })
+                    .collect(),
+                children: Vec::new(),
+                links: dir.links,
+                boundary: dir.boundary,
+                unexpanded: dir.unexpanded,
+            });
+        }
+        Ok(v10::Snapshot {
+            header: self.header,
+            names: names.names,
+            dirs: records,
+            errors: self.errors,
+        })
+    }
+}
@@ -0,0 +1,158 @@
+//! 名称的转义形式
+//!
+//! 映射文件中保存系统原始字节, 内存中的名称一律为转义形式: 非法字节写作 `\xNN`,
+//! 反斜杠写作 `\\`. 转义对所有名称生效, 因此字面包含 `\xNN` 的合法名称与含非法字节的名称
+//! 不会显示为同一个名称, 并且总能还原出原始字节. 不是合法 UTF-8 的名称另在条目上标记为原始名称
+
+use std::{
+    borrow::Cow,
+    ffi::{OsStr, OsString},
+    path::{Component, Path},
+};
+
+/// 系统名称转为映射中的名称, 返回名称与是否为原始名称
+pub(crate) fn from_os(name: &OsStr) -> (String, bool) {
+    (escape(name.as_encoded_bytes()), name.to_str().is_none())
+}
+
+/// 扫描根目录下的系统路径转为相对根目录、以 `/` 分隔的路径, 与目录的键一致,
+/// 各级名称按 [`from_os`] 转义, 用于错误与忽略文件的路径
+pub(crate) fn relative_path(root: &Path, path: &Path) -> String {
+    let mut escaped = String::new();
+    for component in path.strip_prefix(root).unwrap_or(path).components() {
+        match component {
+            Component::Prefix(prefix) => escaped.push_str(&prefix.as_os_str().to_string_lossy()),
+            Component::RootDir => escaped.push('/'),
+            component => {
+                if !escaped.is_empty() && !escaped.ends_with('/') {
+                    escaped.push('/');
+                }
+                match component {
+                    Component::Normal(name) => escaped.push_str(&from_os(name).0),
+                    component => escaped.push_str(&component.as_os_str().to_string_lossy()),
+                }
+            }
+        }
+    }
+    escaped
+}
+
+/// 映射文件中的字节还原为名称, 返回名称与是否为原始名称
+pub(crate) fn from_bytes(bytes: Vec<u8>) -> (String, bool) {
+    match String::from_utf8(bytes) {
+        Ok(name) if !name.contains('\\') => (name, false),
+        Ok(name) => (escape(name.as_bytes()), false),
+        Err(e) => (escape(e.as_bytes()), true),
+    }
+}
+
+/// 转义形式的名称还原为原始字节, 不含 `\` 时即名称本身
+pub(crate) fn to_bytes(name: &str) -> Cow<'_, [u8]> {
+    if name.contains('\\') {
+        Cow::Owned(unescape(name))
+    } else {
+        Cow::Borrowed(name.as_bytes())
+    }
+}
+
+/// 调用方提供的名称转为规范的转义形式, 与编码再解码后的名称相同
+///
+/// 不构成 `\\` 或 `\xNN` 的反斜杠按字面处理, 表示合法 UTF-8 字节的 `\xNN` 还原为字符
+pub(crate) fn normalize(name: &str) -> Cow<'_, str> {
+    if name.contains('\\') {
+        Cow::Owned(from_bytes(unescape(name)).0)
+    } else {
+        Cow::Borrowed(name)
+    }
+}
+
+/// 转义形式的名称是否表示不是合法 UTF-8 的原始名称
+pub(crate) fn is_raw(name: &str) -> bool {
+    std::str::from_utf8(&to_bytes(name)).is_err()
+}
+
+/// 原始字节还原为系统名称, 非 Unix 平台上非法字节按替换字符处理
+pub(crate) fn to_os(bytes: Cow<'_, [u8]>) -> OsString {
+    #[cfg(unix)]
+    {
+        use std::os::unix::ffi::OsStringExt;
+        OsString::from_vec(bytes.into_owned())
+    }
+    #[cfg(not(unix))]
+    {
+        OsString::from(String::from_utf8_lossy(&bytes).into_owned())
+    }
+}
+
+fn escape(bytes: &[u8]) -> String {
+    let mut escaped = String::with_capacity(bytes.len());
+    for chunk in bytes.utf8_chunks() {
+        escaped.push_str(&chunk.valid().replace('\\', "\\\\"));
+        for byte in chunk.invalid() {
+            escaped.push_str(&format!("\\x{byte:02X}"));
+        }
+    }
+    escaped
+}
+
+fn unescape(name: &str) -> Vec<u8> {
+    let mut bytes = Vec::with_capacity(name.len());
+    let mut rest = name.as_bytes();
+    while let Some((&byte, tail)) = rest.split_first() {
+        rest = tail;
+        if byte != b'\\' {
+            bytes.push(byte);
+            continue;
+        }
+        match rest {
+            [b'\\', tail @ ..] => {
+                bytes.push(b'\\');
+                rest = tail;
+            }
+            [b'x', hi, lo, tail @ ..] => {
+                let hex = std::str::from_utf8(&[*hi, *lo])
+                    .ok()
+                    .and_then(|hex| u8::from_str_radix(hex, 16).ok());
+                match hex {
+                    Some(byte) => {
+                        bytes.push(byte);
+                        rest = tail;
+                    }
+                    None => bytes.push(b'\\'),
+                }
+            }
+            _ => bytes.push(b'\\'),
+        }
+    }
+    bytes
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_escape() {
+        for raw in [&b"a\xffb"[..], b"\\x41\xfe", b"\xe4\xb8\xad\xe6", b"\xff\\"] {
+            let (name, is_raw) = from_bytes(raw.to_vec());
+            assert!(is_raw);
+            assert_eq!(to_bytes(&name), raw);
+        }
+        assert_eq!(from_bytes(b"a\xffb".to_vec()).0, "a\\xFFb");
+        assert_eq!(
+            from_bytes("中\\".as_bytes().to_vec()),
+            ("中\\\\".to_string(), false)
+        );
+
+        // 字面包含 `\xFE` 的合法名称与含非法字节的名称转义后不同
+        let (literal, raw) = from_bytes(b"a\\xFE.png".to_vec());
+        assert_eq!((literal.as_str(), raw), ("a\\\\xFE.png", false));
+        assert_ne!(literal, from_bytes(b"a\xfe.png".to_vec()).0);
+        assert_eq!(to_bytes(&literal), &b"a\\xFE.png"[..]);
+        assert!(!is_raw(&literal) && is_raw("a\\xFE.png"));
+
+        assert_eq!(normalize("a\\b"), "a\\\\b");
+        assert_eq!(normalize("a\\\\b"), "a\\\\b");
+        assert_eq!(normalize("\\x41\\xfe"), "A\\xFE");
+    }
+}
@@ -0,0 +1,274 @@
+//! 图片感知哈希与相似图片分组
+//!
+//! 感知哈希由缩小后的灰度图计算, 重新编码、缩放或轻微调色后的图片哈希相近.
+//! 分组时用 BK 树查找汉明距离不超过阈值的图片, 相连的图片经并查集归入同一组
+
+use std::collections::HashMap;
+
+use image::{GrayImage, imageops::FilterType};
+
+use crate::{DirMap, PerceptualHash, join};
+
+/// 解码图片并计算感知哈希, 无法解码时为 `None`; 动画只取第一帧
+pub(crate) fn compute(data: &[u8], algorithm: PerceptualHash) -> Option<u64> {
+    let image = image::load_from_memory(data).ok()?.to_luma8();
+    let shrink =
+        |width, height| image::imageops::resize(&image, width, height, FilterType::Triangle);
+    Some(match algorithm {
+        PerceptualHash::Average => average_hash(&shrink(8, 8)),
+        PerceptualHash::Difference => difference_hash(&shrink(9, 8)),
+        PerceptualHash::Dct => dct_hash(&shrink(32, 32)),
+    })
+}
+
+/// 高位在前逐位拼接
+fn to_bits(bits: impl Iterator<Item = bool>) -> u64 {
+    bits.fold(0, |hash, bit| hash << 1 | bit as u64)
+}
+
+fn average_hash(image: &GrayImage) -> u64 {
+    let sum: u32 = image.pixels().map(|pixel| pixel[0] as u32).sum();
+    let mean = sum / 64;
+    to_bits(image.pixels().map(|pixel| pixel[0] as u32 > mean))
+}
+
+fn difference_hash(image: &GrayImage) -> u64 {
+    to_bits(
+        (0..8).flat_map(|y| {
+            (0..8).map(move |x| image.get_pixel(x, y)[0] > image.get_pixel(x + 1, y)[0])
+        }),
+    )
+}
+
+/// 只计算左上角 8x8 的低频系数, 与除直流分量外系数的中位数比较
+fn dct_hash(image: &GrayImage) -> u64 {
+    let cos: Vec<[f64; 32]> = (0..8)
+        .map(|u| {
+            std::array::from_fn(|x| {
+                ((2 * x + 1) as f64 * u as f64 * std::f64::consts::PI / 64.0).cos()
+            })
+        })
+        .collect();
+    // 先对每一行变换, 再对各列变换
+    let rows: Vec<[f64; 8]> = (0..32)
+        .map(|y| {
+            std::array::from_fn(|u| {
+                (0..32)
+                    .map(|x| image.get_pixel(x, y)[0] as f64 * cos[u][x as usize])
+                    .sum()
+            })
+        })
+        .collect();
+    let coefficients: Vec<f64> = (0..8)
+        .flat_map(|v| {
+            let cos = &cos;
+            let rows = &rows;
+            (0..8).map(move |u| (0..32).map(|y| rows[y][u] * cos[v][y]).sum())
+        })
+        .collect();
+
+    let mut sorted = coefficients[1..].to_vec();
+    sorted.sort_by(f64::total_cmp);
+    let median = sorted[sorted.len() / 2];
+    to_bits(coefficients.iter().map(|&c| c > median))
+}
+
+/// 以汉明距离为度量的 BK 树, 哈希相同的图片共用一个节点
+#[derive(Default)]
+struct BkTree {
+    /// (哈希, 图片下标, 子节点: 距离 -> 节点下标)
+    nodes: Vec<(u64, Vec<usize>, HashMap<u32, usize>)>,
+}
+
+impl BkTree {
+    fn insert(&mut self, hash: u64, item: usize) {
+        if self.nodes.is_empty() {
+            self.nodes.push((hash, vec![item], HashMap::new()));
+            return;
+        }
+        let mut node = 0;
+        loop {
+            let distance = (self.nodes[node].0 ^ hash).count_ones();
+            if distance == 0 {
+                self.nodes[node].1.push(item);
+                return;
+            }
+            match self.nodes[node].2.get(&distance) {
+                Some(&child) => node = child,
+                None => {
+                    let child = self.nodes.len();
+                    self.nodes.push((hash, vec![item], HashMap::new()));
+                    self.nodes[node].2.insert(distance, child);
+                    return;
+                }
+            }
+        }
+    }
+
+    /// 距离不超过 `max_distance` 的所有图片
+    fn find(&self, hash: u64, max_distance: u32, found: &mut Vec<usize>) {
+        let mut pending = vec![0];
+        while let Some(node) = pending.pop() {
+            let Some((node_hash, items, children)) = self.nodes.get(node) else {
+                continue;
+            };
+            let distance = (node_hash ^ hash).count_ones();
+            if distance <= max_distance {
+                found.extend(items);
+            }
+            // 三角不等式: 只有与当前节点距离在 [d - r, d + r] 内的子树可能命中
+            let range =
+                distance.saturating_sub(max_distance)..=distance.saturating_add(max_distance);
+            pending.extend(
+                children
+                    .iter()
+                    .filter(|(edge, _)| range.contains(edge))
+                    .map(|(_, &child)| child),
+            );
+        }
+    }
+}
+
+fn find_root(parents: &mut [usize], mut item: usize) -> usize {
+    while parents[item] != item {
+        parents[item] = parents[parents[item]];
+        item = parents[item];
+    }
+    item
+}
+
+impl DirMap {
+    /// 感知哈希的汉明距离不超过 `max_distance` 的图片分组, 经由相似图片间接相连的也归入同一组
+    ///
+    /// 每组按路径排序, 各组按首个路径排序; 同一物理文件的多个硬链接只取计入大小的那个路径
+    pub fn similar_images(&self, max_distance: u32) -> Vec<Vec<String>> {
+        // 64 位哈希的距离不超过 64
+        let max_distance = max_distance.min(64);
+        let mut images = Vec::new();
+        for (id, dir) in self.iter() {
+            for file in dir.files() {
+                if file.hardlink().is_some_and(|link| !link.is_primary()) {
+                    continue;
+                }
+                if let Some(hash) = file.perceptual_hash() {
+                    images.push((hash, id, file.name()));
+                }
+            }
+        }
+
+        let mut tree = BkTree::default();
+        for (index, &(hash, ..)) in images.iter().enumerate() {
+            tree.insert(hash, index);
+        }
+        let mut parents: Vec<usize> = (0..images.len()).collect();
+        let mut found = Vec::new();
+        for (index, &(hash, ..)) in images.iter().enumerate() {
+            found.clear();
+            tree.find(hash, max_distance, &mut found);
+            for &other in &found {
+                let (a, b) = (
+                    find_root(&mut parents, index),
+                    find_root(&mut parents, other),
+                );
+                parents[a.max(b)] = a.min(b);
+            }
+        }
+
+        let mut groups: HashMap<usize, Vec<String>> = HashMap::new();
+        for (index, &(_, id, name)) in images.iter().enumerate() {
+            let root = find_root(&mut parents, index);
+            groups
+                .entry(root)
+                .or_default()
+                .push(join(&self.path(id), name));
+        }
+        let mut groups: Vec<_> = groups
+            .into_values()
+            .filter(|paths| paths.len() > 1)
+            .map(|mut paths| {
+                paths.sort();
+                paths
+            })
+            .collect();
+        groups.sort();
+        groups
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{fs, io::Cursor};
+
+    use image::{ImageFormat, Rgb, RgbImage};
+
+    use super::*;
+    use crate::{ScanOptions, scan};
+
+    /// 由 `seed` 决定各格亮度的 16x16 方格图
+    fn picture(width: u32, height: u32, seed: u32) -> RgbImage {
+        RgbImage::from_fn(width, height, |x, y| {
+            let (bx, by) = (x * 16 / width, y * 16 / height);
+            let level = (bx.wrapping_mul(73_856_093) ^ by.wrapping_mul(19_349_663) ^ seed)
+                .wrapping_mul(2_654_435_761)
+                >> 24;
+            Rgb([level as u8, level as u8, (255 - level) as u8])
+        })
+    }
+
+    fn encode(image: &RgbImage, format: ImageFormat) -> Vec<u8> {
+        let mut data = Cursor::new(Vec::new());
+        image.write_to(&mut data, format).expect("编码图片失败");
+        data.into_inner()
+    }
+
+    #[test]
+    fn test_similar_images() {
+        let tmp = tempfile::tempdir().expect("创建临时目录失败");
+        fs::create_dir(tmp.path().join("copies")).expect("创建目录失败");
+        let original = picture(320, 240, 1);
+        for (path, data) in [
+            ("original.png", encode(&original, ImageFormat::Png)),
+            (
+                "copies/small.jpg",
+                encode(&picture(160, 120, 1), ImageFormat::Jpeg),
+            ),
+            (
+                "copies/other.png",
+                encode(&picture(320, 240, 2), ImageFormat::Png),
+            ),
+        ] {
+            fs::write(tmp.path().join(path), data).expect("写入文件失败");
+        }
+        fs::write(tmp.path().join("broken.png"), "not a png").expect("写入文件失败");
+
+        let start = tmp.path().to_str().expect("路径无效");
+        for algorithm in [
+            PerceptualHash::Average,
+            PerceptualHash::Difference,
+            PerceptualHash::Dct,
+        ] {
+            let options = ScanOptions {
+                perceptual: Some(algorithm),
+                ..Default::default()
+            };
+            let dirs = scan(start, options).expect("扫描失败");
+            let dirs = crate::unmap(&dirs.encode().expect("编码失败")).expect("解映射失败");
+            assert!(
+                dirs.file("broken.png")
+                    .expect("文件不存在")
+                    .perceptual_hash()
+                    .is_none()
+            );
+            assert_eq!(
+                dirs.similar_images(6),
+                [vec![
+                    "copies/small.jpg".to_string(),
+                    "original.png".to_string()
+                ]],
+                "{algorithm}"
+            );
+            assert!(dirs.similar_images(64).iter().any(|group| group.len() == 3));
+            assert_eq!(dirs.similar_images(u32::MAX), dirs.similar_images(64));
+        }
+    }
+}
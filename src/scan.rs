@@ -0,0 +1,1323 @@
+//! 遍历文件系统生成目录表
+//!
+//! 每个目录由一个 rayon 任务读取, 子目录作为新任务派发, 目录读完即写入目录表,
+//! 不保留遍历到的条目, 内存占用只与目录表本身和尚未读取的目录数量有关
+
+use std::{
+    cell::{RefCell, RefMut},
+    collections::{HashMap, HashSet},
+    fs, io,
+    path::{Path, PathBuf},
+    sync::{
+        Arc,
+        atomic::{AtomicBool, AtomicU32, Ordering},
+    },
+};
+
+use anyhow::{Context, Result, anyhow, bail};
+use parking_lot::Mutex;
+use path_slash::PathExt;
+use rayon::Scope;
+use thread_local::ThreadLocal;
+
+use crate::{
+    Boundary, Dir, DirId, DirMap, ErrorKind, File, FileKind, HardLink, Header, KindTotals, Link,
+    Metadata, ScanError, ScanOptions, SymlinkPolicy, Totals, digest,
+    filter::{Filter, Ignores},
+    hardlink_groups, image,
+    kind::{self, Classifier},
+    name, path_of, phash,
+    stats::Tally,
+};
+
+/// 扫描目录, 返回未编码的目录表
+pub fn scan(start_path: &str, options: ScanOptions) -> Result<DirMap> {
+    // 去掉末尾的 `/`, 保证根目录的键与子路径的父目录一致
+    let start_path: PathBuf = Path::new(start_path).components().collect();
+    let filter = Filter::new(&start_path, &options)?;
+    let (tree, errors) = build_tree(&start_path, &options, &filter)?;
+    let root = start_path.to_slash_lossy().into_owned();
+    let mut header = Header::new(root, options);
+    header.ignore_rules = filter.into_ignore_rules();
+    DirMap::with_totals(header, tree, errors)
+}
+
+fn build_tree(
+    start_path: &Path,
+    options: &ScanOptions,
+    filter: &Filter,
+) -> Result<(Vec<Dir>, Vec<ScanError>)> {
+    let metadata = fs::metadata(start_path)
+        .with_context(|| format!("无法读取起始路径: {}", start_path.display()))?;
+    if !metadata.is_dir() {
+        bail!("起始路径不是目录: {}", start_path.display());
+    }
+
+    let follow = options.symlinks == SymlinkPolicy::Follow;
+    let walker = Walker {
+        root: start_path,
+        options,
+        filter,
+        classifier: Classifier::new(&options.categories)?,
+        root_device: options
+            .one_file_system
+            .then(|| device_id(start_path))
+            .flatten(),
+        next_id: AtomicU32::new(1),
+        shards: ThreadLocal::new(),
+        failed: AtomicBool::new(false),
+        failure: Mutex::new(None),
+    };
+    let root = Task {
+        id: DirId::ROOT,
+        name: Arc::from(""),
+        raw: false,
+        path: start_path.to_path_buf(),
+        depth: 0,
+        ignores: Ignores::default(),
+        ancestors: Ancestors::default().push(start_path, follow),
+        cutoff: None,
+    };
+    rayon::scope(|scope| walker.enter(scope, root));
+
+    if let Some(err) = walker.failure.into_inner() {
+        return Err(err);
+    }
+    let count = walker.next_id.into_inner() as usize;
+    let Shard {
+        dirs: read,
+        mut errors,
+        unexpanded,
+        mut hidden_hardlinks,
+    } = walker
+        .shards
+        .into_iter()
+        .map(RefCell::into_inner)
+        .reduce(Shard::merge)
+        .unwrap_or_default();
+
+    let mut slots: Vec<Option<Dir>> = (0..count).map(|_| None).collect();
+    for (id, dir) in read {
+        slots[id.index()] = Some(dir);
+    }
+    let mut tallies = HashMap::new();
+    for (id, (totals, tally)) in unexpanded {
+        slots[id.index()]
+            .as_mut()
+            .ok_or_else(|| anyhow!("目录未读取: #{}", id.0))?
+            .unexpanded += totals;
+        tallies.insert(id, tally);
+    }
+    let (mut dirs, remap) = renumber(slots)?;
+    let mut tallies: HashMap<_, _> = tallies
+        .into_iter()
+        .map(|(id, tally)| (remap[id.index()], tally))
+        .collect();
+
+    // 超过最大深度的硬链接只计入路径排序后的首个未展开目录, 已有路径在展开的目录中时不再计入
+    if !hidden_hardlinks.is_empty() {
+        let visible: HashSet<_> = dirs
+            .iter()
+            .flat_map(|dir| dir.file.iter().filter_map(|file| file.hardlink))
+            .map(|link| link.id())
+            .collect();
+        hidden_hardlinks.retain(|link| !visible.contains(&link.id));
+    }
+    let mut cutoffs = HashMap::new();
+    for link in &mut hidden_hardlinks {
+        link.cutoff = remap[link.cutoff.index()];
+        cutoffs
+            .entry(link.cutoff)
+            .or_insert_with(|| path_of(&dirs, link.cutoff));
+    }
+    hidden_hardlinks.sort_by(|a, b| (a.id, &cutoffs[&a.cutoff]).cmp(&(b.id, &cutoffs[&b.cutoff])));
+    hidden_hardlinks.dedup_by_key(|link| link.id);
+    for link in hidden_hardlinks {
+        let dir = &mut dirs[link.cutoff.index()];
+        dir.unexpanded.size += link.size;
+        dir.unexpanded.disk_size += link.disk_size;
+        // 文件数在扫描时已计入
+        tallies.entry(link.cutoff).or_default().add_entry(
+            link.typ,
+            &link.name,
+            KindTotals {
+                size: link.size,
+                disk_size: link.disk_size,
+                ..Default::default()
+            },
+        );
+    }
+    for (id, mut tally) in tallies {
+        dirs[id.index()].unexpanded_stats = Some(Box::new(tally.finish()));
+    }
+    dedupe_hardlinks(&mut dirs);
+    errors.extend(digest::hash_files(
+        start_path,
+        &mut dirs,
+        options.hash,
+        options.tolerant,
+    )?);
+    errors.sort_by(|a, b| a.path().cmp(b.path()));
+    Ok((dirs, errors))
+}
+
+/// 并行扫描的共享状态
+struct Walker<'a> {
+    /// 扫描起始路径, 错误路径相对于它记录
+    root: &'a Path,
+    options: &'a ScanOptions,
+    filter: &'a Filter,
+    classifier: Classifier,
+    root_device: Option<u64>,
+    /// 下一个目录编号, 父目录派发任务时为子目录分配, 合并后按遍历顺序重新编号
+    next_id: AtomicU32,
+    /// 每个工作线程只写入自己的分片, 扫描结束后合并, 遍历期间没有全局锁
+    shards: ThreadLocal<RefCell<Shard>>,
+    /// 非容错扫描遇到错误后置位, 其余任务直接返回
+    failed: AtomicBool,
+    failure: Mutex<Option<anyhow::Error>>,
+}
+
+/// 一个工作线程读取到的目录与错误
+#[derive(Default)]
+struct Shard {
+    dirs: Vec<(DirId, Dir)>,
+    errors: Vec<ScanError>,
+    /// 超过最大深度的条目按未展开目录汇总的统计与分类统计, 不含硬链接的大小
+    unexpanded: HashMap<DirId, (Totals, Tally)>,
+    /// 超过最大深度的硬链接, 合并时去重
+    hidden_hardlinks: Vec<HiddenHardlink>,
+}
+
+struct HiddenHardlink {
+    id: (u64, u64),
+    cutoff: DirId,
+    typ: u8,
+    name: Arc<str>,
+    size: u64,
+    disk_size: u64,
+}
+
+impl Shard {
+    fn merge(mut self, other: Shard) -> Shard {
+        self.dirs.extend(other.dirs);
+        self.errors.extend(other.errors);
+        for (id, (totals, tally)) in other.unexpanded {
+            let entry = self.unexpanded.entry(id).or_default();
+            entry.0 += totals;
+            entry.1.merge(tally);
+        }
+        self.hidden_hardlinks.extend(other.hidden_hardlinks);
+        self
+    }
+}
+
+/// 待读取的目录
+struct Task {
+    /// 超过最大深度的目录不写入目录表, 沿用未展开目录的编号
+    id: DirId,
+    name: Arc<str>,
+    /// 名称不是合法 UTF-8
+    raw: bool,
+    path: PathBuf,
+    depth: usize,
+    /// 父目录及以上的忽略规则
+    ignores: Ignores,
+    ancestors: Ancestors,
+    /// 超过最大深度时, 内容汇总到的未展开目录
+    cutoff: Option<DirId>,
+}
+
+/// 跟随符号链接时祖先目录的规范路径, 用于检测循环
+#[derive(Clone, Default)]
+struct Ancestors(Option<Arc<(PathBuf, Ancestors)>>);
+
+impl Ancestors {
+    fn push(&self, path: &Path, follow: bool) -> Self {
+        if !follow {
+            return self.clone();
+        }
+        match fs::canonicalize(path) {
+            Ok(canonical) => Ancestors(Some(Arc::new((canonical, self.clone())))),
+            Err(_) => self.clone(),
+        }
+    }
+
+    fn contains(&self, path: &Path) -> bool {
+        let Ok(canonical) = fs::canonicalize(path) else {
+            return false;
+        };
+        let mut current = &self.0;
+        while let Some(node) = current {
+            if node.0 == canonical {
+                return true;
+            }
+            current = &node.1.0;
+        }
+        false
+    }
+}
+
+/// 目录中的一个条目, 符号链接已按策略解析
+enum Entry {
+    Dir,
+    File(fs::Metadata),
+    Link,
+}
+
+impl<'a> Walker<'a> {
+    fn spawn<'s>(&'s self, scope: &Scope<'s>, task: Task) {
+        scope.spawn(move |scope| {
+            if self.failed.load(Ordering::Relaxed) {
+                return;
+            }
+            if let Err(e) = self.read_dir(scope, task) {
+                self.failed.store(true, Ordering::Relaxed);
+                self.failure.lock().get_or_insert(e);
+            }
+        });
+    }
+
+    /// 读取一个目录, 直接文件写入目录表, 子目录派发为新任务
+    fn read_dir<'s>(&'s self, scope: &Scope<'s>, task: Task) -> Result<()> {
+        let follow = self.options.symlinks == SymlinkPolicy::Follow;
+        let ignores = match self.filter.enter_dir(&task.path, &task.ignores) {
+            Ok(ignores) => ignores,
+            Err(e) => {
+                let kind = e.downcast_ref::<io::Error>().map(io::Error::kind);
+                self.skip(&task.path, kind, format!("{e:#}"))?;
+                task.ignores.clone()
+            }
+        };
+
+        let mut dir = Dir {
+            name: task.name.clone(),
+            raw: task.raw,
+            metadata: self.dir_metadata(&task.path),
+            ..Default::default()
+        };
+        let mut hidden = (Totals::default(), Tally::default());
+        let entries = match fs::read_dir(&task.path) {
+            Ok(entries) => entries,
+            Err(e) => {
+                self.skip_io(&task.path, &e)?;
+                return self.finish(&task, dir, hidden);
+            }
+        };
+        for entry in entries {
+            let entry = match entry {
+                Ok(entry) => entry,
+                Err(e) => {
+                    self.skip_io(&task.path, &e)?;
+                    continue;
+                }
+            };
+            let path = entry.path();
+            let Some(kind) = self.classify(&entry, &path, &task.ancestors)? else {
+                continue;
+            };
+            if self
+                .filter
+                .is_excluded(&path, matches!(kind, Entry::Dir), &ignores)
+            {
+                continue;
+            }
+
+            let (name, raw) = name::from_os(&entry.file_name());
+            match kind {
+                Entry::File(metadata) => {
+                    let mut file = File::from_metadata(name, raw, &metadata, &self.classifier);
+                    if self.options.metadata {
+                        file.metadata = Some(Box::new(Metadata::from_fs(&metadata)));
+                    }
+                    if task.cutoff.is_none() {
+                        if self.options.sniff {
+                            match kind::sniff(&path) {
+                                Ok(kind) => file.content = Some(kind.code()),
+                                Err(e) => self.skip_io(&path, &e)?,
+                            }
+                        }
+                        if self.options.images || self.options.perceptual.is_some() {
+                            self.read_image(&path, &mut file)?;
+                        }
+                        dir.push_file(file);
+                        continue;
+                    }
+                    let (totals, tally) = &mut hidden;
+                    totals.files += 1;
+                    match (file.hardlink, task.cutoff) {
+                        (Some(link), Some(cutoff)) => {
+                            tally.add_entry(
+                                file.typ,
+                                &file.name,
+                                KindTotals {
+                                    files: 1,
+                                    ..Default::default()
+                                },
+                            );
+                            self.shard().hidden_hardlinks.push(HiddenHardlink {
+                                id: link.id(),
+                                cutoff,
+                                typ: file.typ,
+                                name: file.name,
+                                size: file.size,
+                                disk_size: file.disk_size,
+                            })
+                        }
+                        _ => {
+                            totals.size += file.size;
+                            totals.disk_size += file.disk_size;
+                            tally.add_file(&file);
+                        }
+                    }
+                }
+                Entry::Link if task.cutoff.is_some() => hidden.0.links += 1,
+                Entry::Link => match Link::from_path(&path) {
+                    Ok(link) => dir.links.push(link),
+                    Err(e) => self.skip_io(&path, &e)?,
+                },
+                Entry::Dir => {
+                    let id = match task.cutoff {
+                        Some(cutoff) => cutoff,
+                        None => DirId(self.next_id.fetch_add(1, Ordering::Relaxed)),
+                    };
+                    let child = Task {
+                        id,
+                        name: name.into(),
+                        raw,
+                        depth: task.depth + 1,
+                        ignores: ignores.clone(),
+                        ancestors: task.ancestors.push(&path, follow),
+                        cutoff: task.cutoff,
+                        path,
+                    };
+                    if task.cutoff.is_some() {
+                        hidden.0.dirs += 1;
+                        if !self.is_mount_point(&child.path) {
+                            self.spawn(scope, child);
+                        }
+                        continue;
+                    }
+                    dir.children.push(id);
+                    self.enter(scope, child);
+                }
+            }
+        }
+        self.finish(&task, dir, hidden)
+    }
+
+    /// 目录为挂载点或达到最大深度时直接写入未展开的目录, 否则派发任务
+    fn enter<'s>(&'s self, scope: &Scope<'s>, mut task: Task) {
+        let boundary = if self.is_mount_point(&task.path) {
+            Boundary::MountPoint
+        } else if self.options.max_depth == Some(task.depth) {
+            Boundary::MaxDepth
+        } else {
+            return self.spawn(scope, task);
+        };
+
+        let dir = Dir {
+            name: task.name.clone(),
+            raw: task.raw,
+            boundary: Some(boundary),
+            metadata: self.dir_metadata(&task.path),
+            ..Default::default()
+        };
+        self.shard().dirs.push((task.id, dir));
+        if boundary == Boundary::MaxDepth && self.options.aggregate {
+            task.cutoff = Some(task.id);
+            self.spawn(scope, task);
+        }
+    }
+
+    /// 读完目录后写入当前线程的分片, 超过最大深度的目录只累加未展开目录的统计
+    fn finish(&self, task: &Task, dir: Dir, hidden: (Totals, Tally)) -> Result<()> {
+        let mut shard = self.shard();
+        match task.cutoff {
+            Some(cutoff) => {
+                let (totals, tally) = hidden;
+                let entry = shard.unexpanded.entry(cutoff).or_default();
+                entry.0 += totals;
+                entry.1.merge(tally);
+            }
+            None => shard.dirs.push((task.id, dir)),
+        }
+        Ok(())
+    }
+
+    /// 启用元数据时读取目录自身的元数据, 读取失败时不记录
+    fn dir_metadata(&self, path: &Path) -> Option<Box<Metadata>> {
+        if !self.options.metadata {
+            return None;
+        }
+        fs::metadata(path)
+            .ok()
+            .map(|metadata| Box::new(Metadata::from_fs(&metadata)))
+    }
+
+    fn shard(&self) -> RefMut<'_, Shard> {
+        self.shards.get_or_default().borrow_mut()
+    }
+
+    /// 按符号链接策略解析条目类型, 忽略的条目返回 `None`
+    fn classify(
+        &self,
+        entry: &fs::DirEntry,
+        path: &Path,
+        ancestors: &Ancestors,
+    ) -> Result<Option<Entry>> {
+        let file_type = match entry.file_type() {
+            Ok(file_type) => file_type,
+            Err(e) => return self.skip_io(path, &e).map(|_| None),
+        };
+        if file_type.is_dir() {
+            return Ok(Some(Entry::Dir));
+        }
+        if file_type.is_file() {
+            return match entry.metadata() {
+                Ok(metadata) => Ok(Some(Entry::File(metadata))),
+                Err(e) => self.skip_io(path, &e).map(|_| None),
+            };
+        }
+        if !file_type.is_symlink() {
+            return Ok(None);
+        }
+        match self.options.symlinks {
+            SymlinkPolicy::Skip => Ok(None),
+            SymlinkPolicy::Record => Ok(Some(Entry::Link)),
+            // 目标不存在或形成循环的链接记录为链接条目, 循环另记为扫描错误但不中止扫描
+            SymlinkPolicy::Follow => Ok(match fs::metadata(path) {
+                Ok(metadata) if metadata.is_dir() && !ancestors.contains(path) => Some(Entry::Dir),
+                Ok(metadata) if metadata.is_file() => Some(Entry::File(metadata)),
+                Ok(metadata) if metadata.is_dir() => {
+                    self.shard().errors.push(ScanError::new(
+                        name::relative_path(self.root, path),
+                        ErrorKind::Loop,
+                        "链接指向其祖先目录".to_string(),
+                    ));
+                    Some(Entry::Link)
+                }
+                Ok(_) => None,
+                Err(_) => Some(Entry::Link),
+            }),
+        }
+    }
+
+    /// 读取图片一次, 按选项解析图片信息并计算感知哈希
+    fn read_image(&self, path: &Path, file: &mut File) -> Result<()> {
+        // 内容可以识别时以内容为准
+        let kind = file
+            .content_kind()
+            .filter(|&kind| kind != FileKind::Other)
+            .unwrap_or(file.kind());
+        if !image::is_supported(kind) {
+            return Ok(());
+        }
+        // 感知哈希需要解码像素, 只解析图片信息时跳过像素数据
+        let data = match self.options.perceptual {
+            Some(_) => fs::read(path),
+            None => image::read_header(path, kind),
+        };
+        let data = match data {
+            Ok(data) => data,
+            Err(e) => return self.skip_io(path, &e),
+        };
+        if self.options.images {
+            file.image = image::parse(&data, kind).map(Box::new);
+        }
+        if let Some(algorithm) = self.options.perceptual {
+            file.phash = phash::compute(&data, algorithm);
+        }
+        Ok(())
+    }
+
+    fn is_mount_point(&self, path: &Path) -> bool {
+        self.root_device
+            .is_some_and(|root| device_id(path).is_some_and(|device| device != root))
+    }
+
+    fn skip_io(&self, path: &Path, err: &io::Error) -> Result<()> {
+        self.skip(path, Some(err.kind()), err.to_string())
+    }
+
+    /// 容错扫描时记录无法读取的路径, 否则中止扫描
+    fn skip(&self, path: &Path, kind: Option<io::ErrorKind>, message: String) -> Result<()> {
+        let kind = kind.map_or(ErrorKind::Other, Into::into);
+        let err = ScanError::new(name::relative_path(self.root, path), kind, message);
+        if !self.options.tolerant {
+            bail!("扫描失败: {err}");
+        }
+        self.shard().errors.push(err);
+        Ok(())
+    }
+}
+
+/// 按深度优先顺序重新编号并填写父目录, 使编号与线程调度无关; 同时返回旧编号到新编号的映射
+fn renumber(mut slots: Vec<Option<Dir>>) -> Result<(Vec<Dir>, Vec<DirId>)> {
+    let mut dirs: Vec<Dir> = Vec::with_capacity(slots.len());
+    let mut remap = vec![DirId::ROOT; slots.len()];
+    let mut stack: Vec<(DirId, Option<DirId>)> = vec![(DirId::ROOT, None)];
+    while let Some((old, parent)) = stack.pop() {
+        let mut dir = slots
+            .get_mut(old.index())
+            .and_then(Option::take)
+            .ok_or_else(|| anyhow!("目录未读取: #{}", old.0))?;
+        let id = DirId(dirs.len() as u32);
+        remap[old.index()] = id;
+        if let Some(parent) = parent {
+            dirs[parent.index()].children.push(id);
+        }
+        dir.parent = parent;
+        stack.extend(dir.children.drain(..).rev().map(|child| (child, Some(id))));
+        dirs.push(dir);
+    }
+    Ok((dirs, remap))
+}
+
+/// 同一物理文件只保留排序后的首个路径计入大小, 与 `du` 一致
+fn dedupe_hardlinks(dirs: &mut [Dir]) {
+    let duplicates: Vec<_> = hardlink_groups(dirs)
+        .into_values()
+        .flat_map(|paths| paths.into_iter().skip(1))
+        .collect();
+    for (id, index) in duplicates {
+        let dir = &mut dirs[id.index()];
+        let file = &mut dir.file[index];
+        if let Some(link) = &mut file.hardlink {
+            link.primary = false;
+            dir.own_size -= file.size;
+            dir.own_disk_size -= file.disk_size;
+        }
+    }
+}
+
+#[cfg(unix)]
+fn device_id(path: &Path) -> Option<u64> {
+    use std::os::unix::fs::MetadataExt;
+
+    fs::metadata(path).ok().map(|metadata| metadata.dev())
+}
+
+#[cfg(windows)]
+fn device_id(path: &Path) -> Option<u64> {
+    use winapi_util::{Handle, file};
+
+    let handle = Handle::from_path_any(path).ok()?;
+    file::information(handle)
+        .ok()
+        .map(|info| info.volume_serial_number())
+}
+
+#[cfg(not(any(unix, windows)))]
+fn device_id(_: &Path) -> Option<u64> {
+    None
+}
+
+impl Link {
+    fn from_path(path: &Path) -> io::Result<Self> {
+        let name = path
+            .file_name()
+            .map(|name| name::from_os(name).0)
+            .unwrap_or_default();
+        let target = fs::read_link(path)?;
+        let (target, _) = name::from_os(target.as_os_str());
+        // Windows 上名称不能包含反斜杠, 转义后的 `\\` 都是分隔符
+        #[cfg(not(unix))]
+        let target = target.replace("\\\\", "/");
+        Ok(Link { name, target })
+    }
+}
+
+impl Metadata {
+    #[cfg(unix)]
+    fn from_fs(metadata: &fs::Metadata) -> Self {
+        use std::os::unix::fs::MetadataExt;
+
+        Metadata {
+            mtime: metadata.mtime(),
+            ctime: metadata.ctime(),
+            atime: metadata.atime(),
+            mode: metadata.mode(),
+            uid: metadata.uid(),
+            gid: metadata.gid(),
+        }
+    }
+
+    #[cfg(not(unix))]
+    fn from_fs(metadata: &fs::Metadata) -> Self {
+        use std::time::{SystemTime, UNIX_EPOCH};
+
+        let seconds = |time: io::Result<SystemTime>| {
+            time.ok()
+                .map(|time| match time.duration_since(UNIX_EPOCH) {
+                    Ok(after) => after.as_secs() as i64,
+                    Err(before) => -(before.duration().as_secs() as i64),
+                })
+                .unwrap_or_default()
+        };
+        Metadata {
+            mtime: seconds(metadata.modified()),
+            ctime: seconds(metadata.created()),
+            atime: seconds(metadata.accessed()),
+            mode: if metadata.permissions().readonly() {
+                0o444
+            } else {
+                0o666
+            },
+            uid: 0,
+            gid: 0,
+        }
+    }
+}
+
+impl HardLink {
+    #[cfg(unix)]
+    fn from_metadata(metadata: &fs::Metadata) -> Option<Self> {
+        use std::os::unix::fs::MetadataExt;
+
+        (metadata.nlink() > 1).then(|| HardLink {
+            dev: metadata.dev(),
+            ino: metadata.ino(),
+            primary: true,
+        })
+    }
+
+    #[cfg(not(unix))]
+    fn from_metadata(_: &fs::Metadata) -> Option<Self> {
+        None
+    }
+}
+
+impl File {
+    fn from_metadata(
+        name: String,
+        raw: bool,
+        metadata: &fs::Metadata,
+        classifier: &Classifier,
+    ) -> Self {
+        File {
+            typ: classifier.classify(&name).code(),
+            raw,
+            name: name.into(),
+            size: metadata.len(),
+            disk_size: Self::allocated_size(metadata),
+            hardlink: HardLink::from_metadata(metadata),
+            metadata: None,
+            digest: None,
+            content: None,
+            image: None,
+            phash: None,
+        }
+    }
+
+    #[cfg(unix)]
+    fn allocated_size(metadata: &fs::Metadata) -> u64 {
+        use std::os::unix::fs::MetadataExt;
+
+        // st_blocks 的单位固定为 512 字节, 与文件系统块大小无关
+        metadata.blocks() * 512
+    }
+
+    #[cfg(not(unix))]
+    fn allocated_size(metadata: &fs::Metadata) -> u64 {
+        metadata.len()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{HashMode, unmap};
+
+    #[test]
+    fn test_nested_start_path() {
+        for start in ["src/migrate", "src/migrate/", "./src/migrate"] {
+            let dirs = scan(start, ScanOptions::default()).expect("扫描失败");
+            assert!(dirs.root().file_count() > 0);
+        }
+        assert!(scan("src/lib.rs", ScanOptions::default()).is_err());
+        assert!(scan("no/such/dir", ScanOptions::default()).is_err());
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_tolerant_scan() {
+        use std::{fs, os::unix::fs::PermissionsExt};
+
+        let tmp = tempfile::tempdir().expect("创建临时目录失败");
+        let locked = tmp.path().join("locked");
+        fs::create_dir_all(locked.join("inner")).expect("创建目录失败");
+        fs::write(tmp.path().join("a.txt"), "a").expect("写入文件失败");
+        fs::set_permissions(&locked, fs::Permissions::from_mode(0o000)).expect("修改权限失败");
+        // root 用户不受权限限制
+        let readable = fs::read_dir(&locked).is_ok();
+
+        let start = tmp.path().to_str().expect("路径无效");
+        let strict = scan(start, ScanOptions::default());
+        let tolerant = scan(
+            start,
+            ScanOptions {
+                tolerant: true,
+                ..Default::default()
+            },
+        );
+        fs::set_permissions(&locked, fs::Permissions::from_mode(0o755)).expect("修改权限失败");
+        if readable {
+            return;
+        }
+
+        assert!(strict.is_err());
+        let dirs = tolerant.expect("容错扫描失败");
+        assert_eq!(dirs.errors().len(), 1);
+        assert_eq!(dirs.errors()[0].kind(), ErrorKind::PermissionDenied);
+        assert_eq!(dirs.error_summary()[&ErrorKind::PermissionDenied], 1);
+        assert_eq!(dirs.root().total_size(), 1);
+
+        let decoded = unmap(&dirs.encode().expect("编码失败")).expect("解映射失败");
+        assert_eq!(decoded.errors(), dirs.errors());
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_tolerant_read_link() {
+        use std::{
+            fs,
+            os::unix::fs::{PermissionsExt, symlink},
+        };
+
+        // 没有执行权限的目录可以列出条目, 但无法读取其中的链接
+        let tmp = tempfile::tempdir().expect("创建临时目录失败");
+        let locked = tmp.path().join("locked");
+        fs::create_dir(&locked).expect("创建目录失败");
+        symlink("target", locked.join("link")).expect("创建链接失败");
+        fs::write(tmp.path().join("a.txt"), "a").expect("写入文件失败");
+        fs::set_permissions(&locked, fs::Permissions::from_mode(0o644)).expect("修改权限失败");
+        let readable = fs::read_link(locked.join("link")).is_ok();
+
+        let start = tmp.path().to_str().expect("路径无效");
+        let with = |tolerant| {
+            let options = ScanOptions {
+                tolerant,
+                symlinks: SymlinkPolicy::Record,
+                ..Default::default()
+            };
+            scan(start, options)
+        };
+        let (strict, tolerant) = (with(false), with(true));
+        fs::set_permissions(&locked, fs::Permissions::from_mode(0o755)).expect("修改权限失败");
+        if readable {
+            return;
+        }
+
+        assert!(strict.is_err());
+        let dirs = tolerant.expect("容错扫描失败");
+        assert_eq!(dirs.errors().len(), 1);
+        assert_eq!(dirs.errors()[0].kind(), ErrorKind::PermissionDenied);
+        assert!(dirs.errors()[0].path().ends_with("locked/link"));
+        assert_eq!(dirs.root().total_size(), 1);
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_symlinks() {
+        use std::os::unix::fs::symlink;
+
+        let tmp = tempfile::tempdir().expect("创建临时目录失败");
+        let shared = tmp.path().join("shared");
+        fs::create_dir(&shared).expect("创建目录失败");
+        fs::write(shared.join("a.txt"), "abc").expect("写入文件失败");
+        fs::create_dir(tmp.path().join("work")).expect("创建目录失败");
+        symlink("../shared", tmp.path().join("work/shared")).expect("创建链接失败");
+        symlink("..", tmp.path().join("work/up")).expect("创建链接失败");
+        symlink("missing", tmp.path().join("work/broken")).expect("创建链接失败");
+
+        let start = tmp.path().to_str().expect("路径无效");
+        let with = |symlinks| {
+            let options = ScanOptions {
+                symlinks,
+                ..Default::default()
+            };
+            let dirs = scan(start, options).expect("扫描失败");
+            let work = format!("{start}/work");
+            let work = dirs.get(&work).expect("目录不存在").clone();
+            (dirs, work)
+        };
+
+        let (dirs, work) = with(SymlinkPolicy::Skip);
+        assert_eq!(work.links().len(), 0);
+        assert_eq!(dirs.root().total_size(), 3);
+
+        let (dirs, work) = with(SymlinkPolicy::Record);
+        let mut links: Vec<_> = work.links().map(|l| (l.name(), l.target())).collect();
+        links.sort();
+        assert_eq!(
+            links,
+            [("broken", "missing"), ("shared", "../shared"), ("up", "..")]
+        );
+        assert_eq!(dirs.root().total_link_count(), 3);
+        assert_eq!(dirs.header().options().symlinks, SymlinkPolicy::Record);
+
+        let (dirs, work) = with(SymlinkPolicy::Follow);
+        let mut links: Vec<_> = work.links().map(Link::name).collect();
+        links.sort();
+        assert_eq!(links, ["broken", "up"]);
+        let loops: Vec<_> = dirs
+            .errors()
+            .iter()
+            .filter(|err| err.kind() == ErrorKind::Loop)
+            .map(ScanError::path)
+            .collect();
+        assert_eq!(loops, ["work/up"]);
+        assert_eq!(work.total_size(), 3);
+        assert_eq!(dirs.root().total_size(), 6);
+    }
+
+    #[cfg(target_os = "linux")]
+    #[test]
+    fn test_raw_names() {
+        use std::{ffi::OsStr, fs, os::unix::ffi::OsStrExt};
+
+        let tmp = tempfile::tempdir().expect("创建临时目录失败");
+        let dir = tmp.path().join(OsStr::from_bytes(b"d\xff"));
+        fs::create_dir(&dir).expect("创建目录失败");
+        // 最后一个是字面包含 `\xFE` 的合法名称
+        for name in [&b"a\xfe.png"[..], b"a\xff.png", b"a\\.png", b"a\\xFE.png"] {
+            fs::write(dir.join(OsStr::from_bytes(name)), "x").expect("写入文件失败");
+        }
+        std::os::unix::fs::symlink(OsStr::from_bytes(b"a\xfe.png"), dir.join("l\\nk"))
+            .expect("创建符号链接失败");
+        std::os::unix::fs::symlink(".", tmp.path().join(OsStr::from_bytes(b"u\xfe")))
+            .expect("创建符号链接失败");
+
+        let start = tmp.path().to_str().expect("路径无效");
+        let options = ScanOptions {
+            symlinks: SymlinkPolicy::Record,
+            ..Default::default()
+        };
+        let dirs = scan(start, options).expect("扫描失败");
+        let dirs = unmap(&dirs.encode().expect("编码失败")).expect("解映射失败");
+        let (id, sub) = dirs.children(start).next().expect("子目录不存在");
+        assert!(sub.is_raw_name());
+        assert_eq!(sub.name(), "d\\xFF");
+        assert_eq!(dirs.os_path(id), dir);
+
+        let mut names: Vec<_> = sub
+            .files()
+            .map(|f| {
+                (
+                    f.raw_name().into_owned(),
+                    f.is_raw_name(),
+                    f.kind().is_image(),
+                )
+            })
+            .collect();
+        names.sort();
+        assert_eq!(
+            names,
+            [
+                (b"a\\.png".to_vec(), false, true),
+                (b"a\\xFE.png".to_vec(), false, true),
+                (b"a\xfe.png".to_vec(), true, true),
+                (b"a\xff.png".to_vec(), true, true),
+            ]
+        );
+        let raw = dirs.file(&format!("{start}/d\\xFF/a\\xFE.png"));
+        assert_eq!(raw.expect("文件不存在").raw_name(), &b"a\xfe.png"[..]);
+        let literal = dirs.file("d\\xFF/a\\\\xFE.png").expect("文件不存在");
+        assert_eq!(literal.raw_name(), &b"a\\xFE.png"[..]);
+
+        let link = sub.links().next().expect("链接不存在");
+        assert_eq!((link.name(), link.target()), ("l\\\\nk", "a\\xFE.png"));
+        assert_eq!(link.raw_target(), &b"a\xfe.png"[..]);
+
+        // 错误路径中的名称同样按转义形式保存
+        let options = ScanOptions {
+            symlinks: SymlinkPolicy::Follow,
+            ..Default::default()
+        };
+        let dirs = scan(start, options).expect("扫描失败");
+        let paths: Vec<_> = dirs.errors().iter().map(|e| e.path()).collect();
+        assert_eq!(paths, ["u\\xFE"]);
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_hardlinks() {
+        let tmp = tempfile::tempdir().expect("创建临时目录失败");
+        fs::create_dir(tmp.path().join("a")).expect("创建目录失败");
+        fs::create_dir(tmp.path().join("b")).expect("创建目录失败");
+        fs::write(tmp.path().join("a/x"), "12345").expect("写入文件失败");
+        fs::hard_link(tmp.path().join("a/x"), tmp.path().join("b/y")).expect("创建硬链接失败");
+        fs::hard_link(tmp.path().join("a/x"), tmp.path().join("b/z")).expect("创建硬链接失败");
+
+        let start = tmp.path().to_str().expect("路径无效");
+        let mut dirs = scan(start, ScanOptions::default()).expect("扫描失败");
+        let root = dirs.root();
+        assert_eq!((root.total_size(), root.total_file_count()), (5, 3));
+        assert_eq!(
+            dirs.get(&format!("{start}/b"))
+                .expect("目录不存在")
+                .own_size(),
+            0
+        );
+        assert_eq!(
+            dirs.hardlinks(),
+            [vec![
+                "a/x".to_string(),
+                "b/y".to_string(),
+                "b/z".to_string()
+            ]]
+        );
+
+        // 删除计入大小的路径后由下一个路径接替
+        dirs.remove_dir(&format!("{start}/a"))
+            .expect("删除目录失败");
+        assert_eq!(dirs.root().total_size(), 5);
+        let y = dirs.file(&format!("{start}/b/y")).expect("文件不存在");
+        assert!(y.hardlink().is_some_and(|link| link.is_primary()));
+        assert_eq!(y.counted_size(), 5);
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_metadata() {
+        use std::{
+            fs,
+            os::unix::fs::{MetadataExt, PermissionsExt},
+            time::{Duration, UNIX_EPOCH},
+        };
+
+        let tmp = tempfile::tempdir().expect("创建临时目录失败");
+        let path = tmp.path().join("old.txt");
+        fs::write(&path, "x").expect("写入文件失败");
+        let file = fs::File::options()
+            .write(true)
+            .open(&path)
+            .expect("打开文件失败");
+        file.set_modified(UNIX_EPOCH + Duration::from_secs(1_000_000_000))
+            .expect("设置修改时间失败");
+        fs::set_permissions(&path, fs::Permissions::from_mode(0o640)).expect("设置权限失败");
+
+        let start = tmp.path().to_str().expect("路径无效");
+        let dirs = scan(start, ScanOptions::default()).expect("扫描失败");
+        assert!(dirs.root().metadata().is_none());
+        assert!(dirs.file("old.txt").and_then(File::metadata).is_none());
+
+        let options = ScanOptions {
+            metadata: true,
+            ..Default::default()
+        };
+        let dirs = scan(start, options).expect("扫描失败");
+        let dirs = unmap(&dirs.encode().expect("编码失败")).expect("解映射失败");
+        let meta = dirs
+            .file("old.txt")
+            .and_then(File::metadata)
+            .expect("未记录元数据");
+        let expected = fs::metadata(&path).expect("读取元数据失败");
+        assert_eq!(meta.mtime, 1_000_000_000);
+        assert_eq!(meta.mode & 0o777, 0o640);
+        assert_eq!((meta.uid, meta.gid), (expected.uid(), expected.gid()));
+        let root = dirs.root().metadata().expect("未记录目录元数据");
+        assert_eq!(
+            root.mtime,
+            fs::metadata(tmp.path()).expect("读取元数据失败").mtime()
+        );
+    }
+
+    #[test]
+    fn test_hash() {
+        let tmp = tempfile::tempdir().expect("创建临时目录失败");
+        std::fs::create_dir(tmp.path().join("sub")).expect("创建目录失败");
+        for (path, content) in [
+            ("a.txt", "same"),
+            ("sub/b.txt", "same"),
+            ("c.txt", "diff"),
+            ("d.txt", "unique"),
+            ("e.txt", ""),
+            ("sub/f.txt", ""),
+        ] {
+            std::fs::write(tmp.path().join(path), content).expect("写入文件失败");
+        }
+
+        let start = tmp.path().to_str().expect("路径无效");
+        let digest = |dirs: &DirMap, path: &str| dirs.file(path).and_then(File::digest).copied();
+        let dirs = scan(start, ScanOptions::default()).expect("扫描失败");
+        assert!(digest(&dirs, "a.txt").is_none());
+
+        let options = ScanOptions {
+            hash: HashMode::Duplicates,
+            ..Default::default()
+        };
+        let dirs = scan(start, options).expect("扫描失败");
+        let dirs = unmap(&dirs.encode().expect("编码失败")).expect("解映射失败");
+        let same = digest(&dirs, "a.txt").expect("未计算摘要");
+        assert_eq!(same.as_bytes(), blake3::hash(b"same").as_bytes());
+        assert_eq!(digest(&dirs, "sub/b.txt"), Some(same));
+        assert_ne!(digest(&dirs, "c.txt"), Some(same));
+        assert!(digest(&dirs, "c.txt").is_some());
+        // 大小唯一或为空的文件不会重复, 不读取内容
+        assert!(digest(&dirs, "d.txt").is_none());
+        assert!(digest(&dirs, "e.txt").is_none());
+
+        let options = ScanOptions {
+            hash: HashMode::All,
+            ..Default::default()
+        };
+        let dirs = scan(start, options).expect("扫描失败");
+        assert!(
+            dirs.iter()
+                .all(|(_, dir)| dir.files().all(|file| file.digest().is_some()))
+        );
+        assert_eq!(
+            digest(&dirs, "e.txt").map(|digest| digest.to_string()),
+            Some(blake3::hash(b"").to_hex().to_string())
+        );
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_disk_size() {
+        let tmp = tempfile::tempdir().expect("创建临时目录失败");
+        let sparse = fs::File::create(tmp.path().join("sparse.img")).expect("创建文件失败");
+        sparse.set_len(64 << 20).expect("设置长度失败");
+        fs::write(tmp.path().join("small.txt"), "x").expect("写入文件失败");
+
+        let dirs = scan(
+            tmp.path().to_str().expect("路径无效"),
+            ScanOptions::default(),
+        )
+        .expect("扫描失败");
+        let root = dirs.root();
+        let img = root.file("sparse.img").expect("文件不存在");
+        assert_eq!(img.size(), 64 << 20);
+        assert!(img.disk_size() < img.size());
+        assert_eq!(root.total_size(), (64 << 20) + 1);
+        assert_eq!(
+            root.total_disk_size(),
+            root.files().map(File::disk_size).sum::<u64>()
+        );
+        assert_eq!(
+            dirs.calc_size().expect("计算大小失败")[DirId::ROOT.index()],
+            root.totals()
+        );
+    }
+
+    #[cfg(target_os = "linux")]
+    #[test]
+    fn test_one_file_system() {
+        // 需要 /dev/shm 单独挂载
+        if device_id(Path::new("/dev")) == device_id(Path::new("/dev/shm")) {
+            return;
+        }
+        let options = ScanOptions {
+            tolerant: true,
+            one_file_system: true,
+            ..Default::default()
+        };
+        let dirs = scan("/dev", options).expect("扫描失败");
+        let shm = dirs.get("/dev/shm").expect("目录不存在");
+        assert_eq!(shm.boundary(), Some(Boundary::MountPoint));
+        assert_eq!((shm.child_count(), shm.file_count()), (0, 0));
+        assert!(dirs.header().options().one_file_system);
+    }
+
+    #[test]
+    fn test_filters() {
+        let tmp = tempfile::tempdir().expect("创建临时目录失败");
+        for dir in ["target", "src/gen"] {
+            fs::create_dir_all(tmp.path().join(dir)).expect("创建目录失败");
+        }
+        for (file, content) in [
+            ("a.rs", "a"),
+            ("b.txt", "b"),
+            ("target/x.rs", "x"),
+            ("src/c.rs", "c"),
+            ("src/keep.txt", "k"),
+            ("src/target", "t"),
+            ("src/gen/d.rs", "d"),
+            (".gitignore", "# 生成的代码\ngen/\n*.txt\n"),
+            ("src/.dirmapignore", "!keep.txt\n"),
+        ] {
+            fs::write(tmp.path().join(file), content).expect("写入文件失败");
+        }
+
+        let start = tmp.path().to_str().expect("路径无效");
+        let files = |options| {
+            let dirs = scan(start, options).expect("扫描失败");
+            let mut files: Vec<_> = dirs
+                .iter()
+                .flat_map(|(id, dir)| {
+                    let path = dirs.full_path(id);
+                    let path = path.strip_prefix(start).unwrap_or(&path).to_string();
+                    dir.files().map(move |f| format!("{path}/{}", f.name()))
+                })
+                .collect();
+            files.sort();
+            (dirs, files)
+        };
+
+        let (dirs, globbed) = files(ScanOptions {
+            include: vec!["*.rs".to_string()],
+            exclude: vec!["target/".to_string()],
+            ..Default::default()
+        });
+        assert_eq!(globbed, ["/a.rs", "/src/c.rs", "/src/gen/d.rs"]);
+        assert!(!dirs.contains(&format!("{start}/target")));
+        assert!(dirs.header().ignore_rules().is_empty());
+
+        // 以 `/` 结尾的模式只匹配目录
+        let (_, dir_only) = files(ScanOptions {
+            exclude: vec!["target/".to_string(), "src/gen/".to_string()],
+            ..Default::default()
+        });
+        assert!(dir_only.contains(&"/src/target".to_string()));
+        assert!(
+            !dir_only
+                .iter()
+                .any(|f| f.starts_with("/target/") || f.contains("/gen/"))
+        );
+
+        let (dirs, ignored) = files(ScanOptions {
+            ignore_files: true,
+            ..Default::default()
+        });
+        assert_eq!(
+            ignored,
+            [
+                "/.gitignore",
+                "/a.rs",
+                "/src/.dirmapignore",
+                "/src/c.rs",
+                "/src/keep.txt",
+                "/src/target",
+                "/target/x.rs"
+            ]
+        );
+        assert!(!dirs.contains(&format!("{start}/src/gen")));
+        let rules = dirs.header().ignore_rules();
+        assert_eq!(rules.len(), 2);
+        assert_eq!(rules[0].path(), ".gitignore");
+        assert_eq!(rules[0].rules(), ["gen/", "*.txt"]);
+
+        let invalid = ScanOptions {
+            exclude: vec!["[".to_string()],
+            ..Default::default()
+        };
+        assert!(scan(start, invalid).is_err());
+    }
+
+    #[test]
+    fn test_max_depth() {
+        let tmp = tempfile::tempdir().expect("创建临时目录失败");
+        fs::create_dir_all(tmp.path().join("a/b/c")).expect("创建目录失败");
+        for (file, content) in [
+            ("z", "1"),
+            ("a/y", "22"),
+            ("a/b/w.txt", "4444"),
+            ("a/b/c/x.gif", "333"),
+        ] {
+            fs::write(tmp.path().join(file), content).expect("写入文件失败");
+        }
+
+        let start = tmp.path().to_str().expect("路径无效");
+        let a = format!("{start}/a");
+        let options = ScanOptions {
+            max_depth: Some(1),
+            ..Default::default()
+        };
+        let dirs = scan(start, options.clone()).expect("扫描失败");
+        assert_eq!(dirs.len(), 2);
+        let unexpanded = dirs.get(&a).expect("目录不存在");
+        assert_eq!(unexpanded.boundary(), Some(Boundary::MaxDepth));
+        assert_eq!((unexpanded.file_count(), unexpanded.child_count()), (0, 0));
+        assert_eq!(dirs.root().total_size(), 1);
+
+        let aggregated = ScanOptions {
+            aggregate: true,
+            ..options
+        };
+        let dirs = scan(start, aggregated).expect("扫描失败");
+        let dirs = unmap(&dirs.encode().expect("编码失败")).expect("解映射失败");
+        assert_eq!(dirs.len(), 2);
+        let unexpanded = dirs.get(&a).expect("目录不存在");
+        assert_eq!(
+            unexpanded.unexpanded_totals(),
+            Totals {
+                size: 9,
+                disk_size: unexpanded.total_disk_size(),
+                files: 3,
+                dirs: 2,
+                links: 0,
+            }
+        );
+        let root = dirs.root();
+        assert_eq!((root.total_size(), root.total_file_count()), (10, 4));
+        assert_eq!(root.total_dir_count(), 3);
+        // 未展开的内容也按类型与扩展名统计
+        let stats = root.kind_stats();
+        assert!(stats.is_complete());
+        assert_eq!(stats.kind(FileKind::Gif).size, 3);
+        assert_eq!(stats.extension("txt").files, 1);
+        assert_eq!(
+            stats.kinds().map(|(_, totals)| totals.files).sum::<u64>(),
+            4
+        );
+
+        // 旧格式没有未展开内容的分类统计
+        let id = dirs.id("a").expect("目录不存在");
+        let mut old = dirs.dirs.clone();
+        old[id.index()].unexpanded_stats = None;
+        let old = DirMap::with_totals(dirs.header.clone(), old, Vec::new()).expect("计算统计失败");
+        assert!(!old.root().kind_stats().is_complete());
+        assert_eq!(old.root().kind_stats().kinds().count(), 1);
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_hidden_hardlinks() {
+        let tmp = tempfile::tempdir().expect("创建临时目录失败");
+        fs::create_dir_all(tmp.path().join("a/b")).expect("创建目录失败");
+        fs::write(tmp.path().join("x"), "12345").expect("写入文件失败");
+        fs::hard_link(tmp.path().join("x"), tmp.path().join("a/b/y")).expect("创建硬链接失败");
+        fs::write(tmp.path().join("a/b/z"), "123").expect("写入文件失败");
+        fs::hard_link(tmp.path().join("a/b/z"), tmp.path().join("a/w")).expect("创建硬链接失败");
+
+        let start = tmp.path().to_str().expect("路径无效");
+        let options = ScanOptions {
+            max_depth: Some(1),
+            aggregate: true,
+            ..Default::default()
+        };
+        let dirs = scan(start, options).expect("扫描失败");
+        // `a/b/y` 与展开的 `x` 是同一物理文件, `a/w` 与 `a/b/z` 只计一次
+        let unexpanded = dirs.get("a").expect("目录不存在").unexpanded_totals();
+        assert_eq!((unexpanded.size, unexpanded.files), (3, 3));
+        let root = dirs.root();
+        assert_eq!((root.total_size(), root.total_file_count()), (8, 4));
+        let stats = dirs.get("a").expect("目录不存在").kind_stats();
+        assert_eq!(
+            (stats.extension("").size, stats.extension("").files),
+            (3, 3)
+        );
+    }
+
+    #[test]
+    fn test_parallel_scan() {
+        let scan_with = |threads| {
+            let pool = rayon::ThreadPoolBuilder::new()
+                .num_threads(threads)
+                .build()
+                .expect("创建线程池失败");
+            let dirs = pool
+                .install(|| scan("src", ScanOptions::default()))
+                .expect("扫描失败");
+            let mut dirs: Vec<_> = dirs
+                .iter()
+                .map(|(id, dir)| (dirs.path(id), dir.totals(), dir.file_count()))
+                .collect();
+            dirs.sort_by(|a, b| a.0.cmp(&b.0));
+            dirs
+        };
+        assert_eq!(scan_with(1), scan_with(4));
+    }
+}
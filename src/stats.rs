@@ -0,0 +1,317 @@
+//! 按文件类型与扩展名分类的递归统计
+//!
+//! 统计与目录的递归大小在同一遍后序遍历中累加, 以排好序的紧凑数组保存在各目录中,
+//! 相同的扩展名在整个目录表中共用一份字符串. 汇总在未展开目录中的条目没有文件,
+//! 扫描时另行按类型与扩展名统计并写入映射文件
+
+use std::{
+    collections::{HashMap, HashSet},
+    ops::{AddAssign, SubAssign},
+    path::Path,
+    sync::Arc,
+};
+
+use bincode::{Decode, Encode};
+use serde::{Deserialize, Serialize};
+
+use crate::{File, FileKind};
+
+/// 一类文件的数量与大小, 大小不重复计算同一物理文件的多个硬链接
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Encode, Decode, Serialize, Deserialize)]
+pub struct KindTotals {
+    pub files: u64,
+    /// 表观大小
+    pub size: u64,
+    /// 实际占用的磁盘空间
+    pub disk_size: u64,
+}
+
+impl KindTotals {
+    fn of_file(file: &File) -> Self {
+        KindTotals {
+            files: 1,
+            size: file.counted_size(),
+            disk_size: file.counted_disk_size(),
+        }
+    }
+}
+
+impl AddAssign for KindTotals {
+    fn add_assign(&mut self, rhs: Self) {
+        self.files += rhs.files;
+        self.size += rhs.size;
+        self.disk_size += rhs.disk_size;
+    }
+}
+
+impl SubAssign for KindTotals {
+    fn sub_assign(&mut self, rhs: Self) {
+        self.files -= rhs.files;
+        self.size -= rhs.size;
+        self.disk_size -= rhs.disk_size;
+    }
+}
+
+/// 目录下所有文件按扩展名所属类型与小写扩展名分类的统计, 包含所有子孙目录
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct KindStats {
+    /// 按类型编号排序
+    kinds: Box<[(u8, KindTotals)]>,
+    /// 按扩展名排序, 没有扩展名的文件记为空串
+    extensions: Box<[(Arc<str>, KindTotals)]>,
+    /// 子树中有未展开目录的汇总内容没有分类统计
+    incomplete: bool,
+}
+
+impl KindStats {
+    /// 各类型的统计, 按类型编号排序, 不含数量为零的类型
+    pub fn kinds(&self) -> impl ExactSizeIterator<Item = (FileKind, KindTotals)> {
+        self.kinds
+            .iter()
+            .map(|&(code, totals)| (FileKind::from_code(code), totals))
+    }
+
+    pub fn kind(&self, kind: FileKind) -> KindTotals {
+        let code = kind.code();
+        self.kinds
+            .binary_search_by_key(&code, |&(code, _)| code)
+            .map_or_else(|_| KindTotals::default(), |i| self.kinds[i].1)
+    }
+
+    /// 各扩展名的统计, 按扩展名排序, 没有扩展名的文件记为空串
+    pub fn extensions(&self) -> impl ExactSizeIterator<Item = (&str, KindTotals)> {
+        self.extensions
+            .iter()
+            .map(|(ext, totals)| (&**ext, *totals))
+    }
+
+    /// 扩展名不区分大小写, 不含 `.`
+    pub fn extension(&self, ext: &str) -> KindTotals {
+        let ext = ext.to_lowercase();
+        self.extensions
+            .binary_search_by(|(key, _)| (**key).cmp(ext.as_str()))
+            .map_or_else(|_| KindTotals::default(), |i| self.extensions[i].1)
+    }
+
+    /// 分类之和是否等于目录的递归文件数与大小
+    ///
+    /// 旧格式的映射没有记录未展开目录中汇总内容的分类, 这部分只计入目录总计
+    pub fn is_complete(&self) -> bool {
+        !self.incomplete
+    }
+
+    /// 单个文件的统计
+    pub(crate) fn of_file(file: &File) -> Self {
+        Self::of_entry(file.typ, file.name(), KindTotals::of_file(file))
+    }
+
+    /// 按类型编号与文件名记入一项统计
+    pub(crate) fn of_entry(typ: u8, name: &str, totals: KindTotals) -> Self {
+        let mut tally = Tally::default();
+        tally.add_entry(typ, name, totals);
+        tally.finish()
+    }
+
+    pub(crate) fn set_incomplete(&mut self, incomplete: bool) {
+        self.incomplete = incomplete;
+    }
+
+    /// 由解码的统计重建, 条目会重新排序并合并重复项
+    pub(crate) fn from_entries(
+        kinds: impl IntoIterator<Item = (u8, KindTotals)>,
+        extensions: impl IntoIterator<Item = (String, KindTotals)>,
+    ) -> Self {
+        let mut tally = Tally::default();
+        for (code, totals) in kinds {
+            tally.add_kind(code, totals);
+        }
+        for (ext, totals) in extensions {
+            *tally.extensions.entry(Arc::from(ext)).or_default() += totals;
+        }
+        tally.finish()
+    }
+}
+
+impl AddAssign<&KindStats> for KindStats {
+    fn add_assign(&mut self, rhs: &KindStats) {
+        let mut tally = Tally::from(&*self);
+        tally.add(rhs);
+        *self = tally.finish();
+    }
+}
+
+/// 扣除子集的统计, 完整标记由调用方按子树重新确定
+impl SubAssign<&KindStats> for KindStats {
+    fn sub_assign(&mut self, rhs: &KindStats) {
+        let mut tally = Tally::from(&*self);
+        for &(code, totals) in &rhs.kinds {
+            tally.sub_kind(code, totals);
+        }
+        for (ext, totals) in &rhs.extensions {
+            if let Some(entry) = tally.extensions.get_mut(ext) {
+                *entry -= *totals;
+                if *entry == KindTotals::default() {
+                    tally.extensions.remove(ext);
+                }
+            }
+        }
+        *self = tally.finish();
+    }
+}
+
+/// 逐个累加文件与子目录的统计, 完成后转为紧凑的 [`KindStats`]
+///
+/// 累加多个目录时复用同一个实例, 扩展名字符串会在各目录间共用
+#[derive(Default)]
+pub(crate) struct Tally {
+    kinds: HashMap<u8, KindTotals>,
+    extensions: HashMap<Arc<str>, KindTotals>,
+    incomplete: bool,
+    /// 已分配的小写扩展名
+    interned: HashSet<Arc<str>>,
+}
+
+impl Tally {
+    pub(crate) fn add_file(&mut self, file: &File) {
+        self.add_entry(file.typ, file.name(), KindTotals::of_file(file));
+    }
+
+    /// 按类型编号与文件名累加, 用于没有写入目录表的文件
+    pub(crate) fn add_entry(&mut self, typ: u8, name: &str, totals: KindTotals) {
+        self.add_kind(typ, totals);
+        let ext = Path::new(name)
+            .extension()
+            .and_then(|ext| ext.to_str())
+            .unwrap_or_default();
+        if let Some(entry) = self.extensions.get_mut(ext) {
+            *entry += totals;
+            return;
+        }
+        let ext = match self.interned.get(ext) {
+            Some(ext) => ext.clone(),
+            // 扩展名多为小写, 已存在时不必分配
+            None => {
+                let lower = ext.to_lowercase();
+                match self.interned.get(lower.as_str()) {
+                    Some(ext) => ext.clone(),
+                    None => {
+                        let ext: Arc<str> = Arc::from(lower);
+                        self.interned.insert(ext.clone());
+                        ext
+                    }
+                }
+            }
+        };
+        *self.extensions.entry(ext).or_default() += totals;
+    }
+
+    pub(crate) fn add(&mut self, stats: &KindStats) {
+        for &(code, totals) in &stats.kinds {
+            self.add_kind(code, totals);
+        }
+        for (ext, totals) in &stats.extensions {
+            match self.extensions.get_mut(ext) {
+                Some(entry) => *entry += *totals,
+                None => {
+                    self.extensions.insert(ext.clone(), *totals);
+                }
+            }
+        }
+        self.incomplete |= stats.incomplete;
+    }
+
+    pub(crate) fn merge(&mut self, other: Tally) {
+        for (code, totals) in other.kinds {
+            self.add_kind(code, totals);
+        }
+        for (ext, totals) in other.extensions {
+            *self.extensions.entry(ext).or_default() += totals;
+        }
+        self.incomplete |= other.incomplete;
+    }
+
+    pub(crate) fn set_incomplete(&mut self) {
+        self.incomplete = true;
+    }
+
+    fn add_kind(&mut self, code: u8, totals: KindTotals) {
+        *self.kinds.entry(code).or_default() += totals;
+    }
+
+    fn sub_kind(&mut self, code: u8, totals: KindTotals) {
+        if let Some(entry) = self.kinds.get_mut(&code) {
+            *entry -= totals;
+            if *entry == KindTotals::default() {
+                self.kinds.remove(&code);
+            }
+        }
+    }
+
+    /// 取出累加结果并清空, 保留已分配的扩展名
+    pub(crate) fn finish(&mut self) -> KindStats {
+        let mut kinds: Vec<_> = self.kinds.drain().collect();
+        kinds.sort_unstable_by_key(|&(code, _)| code);
+        let mut extensions: Vec<_> = self.extensions.drain().collect();
+        extensions.sort_unstable_by(|a, b| a.0.cmp(&b.0));
+        KindStats {
+            kinds: kinds.into_boxed_slice(),
+            extensions: extensions.into_boxed_slice(),
+            incomplete: std::mem::take(&mut self.incomplete),
+        }
+    }
+}
+
+impl From<&KindStats> for Tally {
+    fn from(stats: &KindStats) -> Self {
+        let mut tally = Tally::default();
+        tally.add(stats);
+        tally
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{DirId, DirMap, File, FileKind, Header, KindTotals, ScanOptions};
+
+    #[test]
+    fn test_kind_stats() {
+        let mut dirs = DirMap::new(Header::new("root".to_string(), ScanOptions::default()));
+        dirs.create_dir("root/a/b").expect("创建目录失败");
+        for (dir, name, size) in [
+            ("root", "x.gif", 1),
+            ("root/a", "y.GIF", 2),
+            ("root/a/b", "z.webp", 4),
+            ("root/a/b", "README", 8),
+        ] {
+            dirs.insert_file(dir, File::new(name, size))
+                .expect("插入文件失败");
+        }
+
+        let stats = dirs.root().kind_stats();
+        assert_eq!(stats.kind(FileKind::Gif).files, 2);
+        assert_eq!(stats.kind(FileKind::Webp).size, 4);
+        assert_eq!(stats.extension("gif").size, 3);
+        assert_eq!(stats.extension("").files, 1);
+        assert!(stats.is_complete());
+        let a = dirs.get("a").expect("目录不存在").kind_stats();
+        assert_eq!(a.kind(FileKind::Gif).size, 2);
+
+        let decoded = crate::unmap(&dirs.encode().expect("编码失败")).expect("解码失败");
+        assert_eq!(decoded.root().kind_stats(), dirs.root().kind_stats());
+
+        dirs.remove_dir("root/a").expect("删除目录失败");
+        let stats = dirs.dir(DirId::ROOT).expect("目录不存在").kind_stats();
+        assert_eq!(
+            stats.kinds().collect::<Vec<_>>(),
+            [(
+                FileKind::Gif,
+                KindTotals {
+                    files: 1,
+                    size: 1,
+                    disk_size: 1
+                }
+            )]
+        );
+        assert_eq!(stats.extensions().count(), 1);
+    }
+}